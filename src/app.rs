@@ -1,15 +1,47 @@
 use crate::app_info::{self, AppInfo, Color};
-use crate::cacher::{AppState, Cacher};
-use crate::opts::{AppCommand, Opts, UpdateCommand};
-use crate::{crates::CratesApi, github::GitHubApi, probe::ProbeTool};
-use anyhow::Error;
+use crate::cacher::{Cacher, DEFAULT_PROFILE};
+use crate::credentials::{self, Credentials, TOKEN_VAR};
+use crate::github::{self, Release};
+use crate::http::{HttpClient, OfflineError, ResponseCache};
+use crate::instance::{Instance, LaunchOptions, Registry};
+use crate::lock::LockMode;
+use crate::logs::{self, LogStore, OutputPump, Stream};
+use crate::opts::{AppCommand, LearnCommand, Opts, RestartCommand, StopCommand, UpdateCommand};
+use crate::opts::{AuthAction, AuthCommand, ImportCommand, ProfileAction, ProfileCommand};
+use crate::opts::{CacheAction, CacheCommand, ConfigAction, ConfigCommand};
+use crate::opts::{CleanCommand, InitCommand, LogsCommand, UninstallCommand};
+use crate::opts::{SnapshotAction, SnapshotCommand};
+use crate::preferences;
+use crate::process::{Shutdown, ShutdownListener};
+use crate::project::{Project, Settings};
+use crate::retention;
+use crate::snapshot::{Change, SnapshotStore};
+use crate::supervisor::{self, CrashTracker, OutputTail};
+use crate::workspace;
+use crate::{built_info, crates::CratesApi, disk, environment, github::GitHubApi};
+use crate::{probe::ProbeTool, process};
+use anyhow::{anyhow as err, Error};
+use chrono::Local;
 use colored::Colorize;
-use dialoguer::Confirm;
+use dialoguer::{Confirm, Password, Select};
 use flate2::read::GzDecoder;
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
 use std::process::Stdio;
 use tar::Archive;
+use tokio::fs;
 use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
 use tokio::{select, signal};
+use toml_edit::DocumentMut;
+
+const PORT: u16 = 6361;
+const MAX_INSTANCES: u16 = 100;
+const OUTPUT_TIMEOUT: Duration = Duration::from_millis(500);
 
 pub struct App {
     opts: Opts,
@@ -17,7 +49,21 @@ pub struct App {
     crates_api: CratesApi,
     github_api: GitHubApi,
     probe_tool: ProbeTool,
+    registry: Registry,
+    log_store: LogStore,
+    snapshot_store: SnapshotStore,
+    credentials: Credentials,
+    http: HttpClient,
     app: Option<Child>,
+    output: Option<OutputPump>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+/// The reason why the supervised app is not running anymore
+enum AppExit {
+    Shutdown(Shutdown),
+    Exited(ExitStatus),
+    Hung,
 }
 
 impl App {
@@ -26,42 +72,136 @@ impl App {
         match &app.opts.command {
             None => {
                 app.command_update(false, None).await?;
-                app.command_learn(ride).await?;
+                app.command_learn(LearnCommand::default(), ride).await?;
             }
             Some(AppCommand::Update(opts)) => {
                 let opts = Some(opts.clone());
                 app.command_update(true, opts).await?;
             }
-            Some(AppCommand::Learn) => {
-                app.command_learn(ride).await?;
+            Some(AppCommand::Learn(opts)) => {
+                let opts = opts.clone();
+                app.command_learn(opts, ride).await?;
             }
             /*
             Some(AppCommand::Stack) => {
                 app.command_stack().await?;
             }
             */
-            Some(AppCommand::Clean) => {
-                app.command_clean().await?;
+            Some(AppCommand::Init(opts)) => {
+                let opts = opts.clone();
+                app.command_init(opts, ride).await?;
+            }
+            Some(AppCommand::Ps) => {
+                app.command_ps().await?;
+            }
+            Some(AppCommand::Stop(opts)) => {
+                let opts = opts.clone();
+                app.command_stop(opts).await?;
+            }
+            Some(AppCommand::Restart(opts)) => {
+                let opts = opts.clone();
+                app.command_restart(opts, ride).await?;
+            }
+            Some(AppCommand::Logs(opts)) => {
+                let opts = opts.clone();
+                app.command_logs(opts).await?;
+            }
+            Some(AppCommand::Snapshot(opts)) => {
+                let opts = opts.clone();
+                app.command_snapshot(opts).await?;
+            }
+            Some(AppCommand::Clean(opts)) => {
+                let opts = opts.clone();
+                app.command_clean(opts).await?;
+            }
+            Some(AppCommand::Uninstall(opts)) => {
+                let opts = opts.clone();
+                app.command_uninstall(opts).await?;
+            }
+            Some(AppCommand::Config(opts)) => {
+                let opts = opts.clone();
+                app.command_config(opts).await?;
+            }
+            Some(AppCommand::Cache(opts)) => {
+                let opts = opts.clone();
+                app.command_cache(opts).await?;
+            }
+            Some(AppCommand::Profile(opts)) => {
+                let opts = opts.clone();
+                app.command_profile(opts).await?;
+            }
+            Some(AppCommand::Auth(opts)) => {
+                let opts = opts.clone();
+                app.command_auth(opts).await?;
+            }
+            Some(AppCommand::Import(opts)) => {
+                let opts = opts.clone();
+                app.command_import(opts).await?;
             }
         }
         Ok(())
     }
 
     async fn init(opts: Opts) -> Result<Self, Error> {
-        let mut cacher = Cacher::create().await?;
+        let mut cacher = Cacher::create(
+            opts.home.as_deref(),
+            opts.system_install,
+            opts.profile.as_deref(),
+        )
+        .await?;
+        if !cacher.profile_exists().await? {
+            let name = cacher.profile();
+            return Err(err!(
+                "The profile {name} doesn't exist, create it with `knowledge profile create {name}`"
+            ));
+        }
         cacher.initialize().await?;
+        let preferences = &cacher.config().preferences;
+        preferences.apply_color();
+        let http = HttpClient::new(&cacher.config().global.network).unwrap_or_else(|err| {
+            let warn = format!("{err}\nThe default network settings are used.");
+            println!("{}", warn.yellow());
+            HttpClient::default()
+        });
+        http.set_offline(opts.offline);
+        let mut github_api = GitHubApi::new(http.clone());
+        github_api.set_channel(preferences.channel);
+        github_api.set_releases_url(preferences.release_url.clone());
+        github_api.set_version_limit(cacher.policy().version.clone());
+        let response_cache = ResponseCache::new(cacher.metadata_dir().clone());
+        github_api.set_cache(response_cache.clone());
+        let mut crates_api = CratesApi::new(http.clone());
+        crates_api.set_cache(response_cache);
+        let credentials = load_credentials(&cacher).await;
+        let token = credentials::github_token(cacher.config(), &credentials);
+        github_api.set_token(token.map(|(token, _source)| token));
+        let registry = Registry::new(cacher.instances_dir().clone());
+        let log_store = LogStore::new(cacher.logs_dir().clone());
+        let excluded = cacher.launcher_dirs().into_iter().cloned().collect();
+        let snapshot_store = SnapshotStore::new(cacher.snapshots_dir().clone(), excluded);
         Ok(Self {
             opts,
             cacher,
-            crates_api: CratesApi::new(),
-            github_api: GitHubApi::new(),
-            probe_tool: ProbeTool::new(),
+            crates_api,
+            github_api,
+            probe_tool: ProbeTool::new(http.clone()),
+            registry,
+            log_store,
+            snapshot_store,
+            credentials,
+            http,
             app: None,
+            output: None,
+            readers: Vec::new(),
         })
     }
 
     async fn update_launcher(&mut self, force: bool) -> Result<(), Error> {
-        if self.cacher.launcher.is_update_required() || force {
+        if !self.cacher.policy().update_notices {
+            return Ok(());
+        }
+        let interval = self.cacher.config().preferences.update_interval();
+        if self.cacher.launcher.is_update_required(interval) || force {
             println!("Checking an update for the launcher...");
             let version = self.crates_api.latest_version().await?;
 
@@ -73,8 +213,9 @@ impl App {
                 println!("{command}");
             }
 
-            self.cacher.launcher.update_check();
-            self.cacher.write_state().await?;
+            self.cacher
+                .update_state(|state| state.launcher.update_check())
+                .await?;
         }
         Ok(())
     }
@@ -84,31 +225,57 @@ impl App {
         force_check: bool,
         update_cmd: Option<UpdateCommand>,
     ) -> Result<(), Error> {
+        let _lock = self.cacher.lock(LockMode::Exclusive).await?;
+        // Another launcher could update the app while this one was waiting
+        self.cacher.reload_state().await?;
         let mut force_reload = false;
         // let mut os = None;
         if let Some(update_cmd) = update_cmd.as_ref() {
             force_reload = update_cmd.force;
             if let Some(os) = &update_cmd.system {
                 // Overrides the system
-                self.cacher.global.system = os.clone();
+                self.cacher.system = os.clone();
+            }
+        }
+        // An administrator could install a newer version for all users
+        let shared = self.cacher.shared_versions(&app_info::LEARN).await?;
+        let policy = self.cacher.policy();
+        if let Some(version) = shared.into_iter().rev().find(|ver| policy.allows(ver)) {
+            if self.cacher.ri_learn.is_outdated(version.clone()) {
+                println!("Switching to the version {version} installed for all users");
+                self.cacher.ri_learn.version = Some(version);
+                self.cacher.write_state().await?;
             }
         }
-        let os = self.cacher.global.system.as_ref();
-        if self.cacher.ri_learn.is_update_required() || force_check {
+        let preferences = &self.cacher.config().preferences;
+        let interval = preferences.update_interval();
+        // The policy could be changed after the installation
+        let denied = self
+            .cacher
+            .ri_learn
+            .version
+            .as_ref()
+            .map(|version| !self.cacher.policy().allows(version))
+            .unwrap_or(false);
+        let check = self.cacher.ri_learn.is_not_exist()
+            || denied
+            || preferences.auto_update && self.cacher.ri_learn.is_allowed_to_check(interval);
+        if check || force_check {
             println!("Checking an update for the app...");
             let latest = self.github_api.latest_release(&app_info::LEARN).await?;
             let version = latest.version.clone();
-            if self.cacher.ri_learn.is_outdated(version) || force_reload {
-                println!("Downloading {}...", latest.version);
-                let url = latest.get_asset_for_os(&app_info::LEARN, os)?;
-                let tar_gz = self.github_api.download_assets(url).await?.into_std().await;
-                println!("Unpacking...");
-                let tar = GzDecoder::new(tar_gz);
-                let mut archive = Archive::new(tar);
-                archive.unpack(self.cacher.bin_dir())?;
-                self.cacher.fix_binaries().await?;
-                self.cacher.ri_learn.version = Some(latest.version);
-                println!("Done");
+            let available = self.cacher.available_versions(&app_info::LEARN).await?;
+            if (self.cacher.ri_learn.is_outdated(version.clone()) || denied)
+                && available.contains(&version)
+                && !force_reload
+            {
+                // Nothing to download, the version was installed for a project
+                // or for all users
+                println!("Switching to the installed version {version}");
+                self.switch_ri_learn(version).await?;
+            } else if self.cacher.ri_learn.is_outdated(version) || force_reload || denied {
+                let version = self.install_ri_learn(latest).await?;
+                self.switch_ri_learn(version).await?;
             }
 
             // Never called if update has failed
@@ -118,9 +285,99 @@ impl App {
         Ok(())
     }
 
+    /// Downloads and installs the release without switching to it
+    async fn install_ri_learn(&mut self, release: Release) -> Result<Version, Error> {
+        let os = self.cacher.system.as_ref();
+        println!("Downloading {}...", release.version);
+        let url = release.get_asset_for_os(&app_info::LEARN, os)?;
+        let tar_gz = self.github_api.download_assets(url).await?.into_std().await;
+        self.unpack_ri_learn(&release.version, tar_gz).await?;
+        Ok(release.version)
+    }
+
+    /// Installs the archive of the version
+    async fn unpack_ri_learn(
+        &mut self,
+        version: &Version,
+        tar_gz: std::fs::File,
+    ) -> Result<(), Error> {
+        println!("Unpacking...");
+        let dir = self.cacher.version_dir(&app_info::LEARN, version);
+        if fs::try_exists(&dir).await? {
+            fs::remove_dir_all(&dir).await?;
+        }
+        let tar = GzDecoder::new(tar_gz);
+        let mut archive = Archive::new(tar);
+        archive.unpack(&dir)?;
+        self.cacher.fix_binaries(&dir).await?;
+        println!("Done");
+        Ok(())
+    }
+
+    /// Makes the version used by default and removes old ones
+    async fn switch_ri_learn(&mut self, version: Version) -> Result<(), Error> {
+        self.cacher.ri_learn.version = Some(version);
+        self.cacher.write_state().await?;
+        if let Err(err) = self.collect_garbage().await {
+            let warn = format!("Can't remove old versions: {err}").yellow();
+            println!("{warn}");
+        }
+        Ok(())
+    }
+
+    /// Applies the retention policy to the cache
+    async fn collect_garbage(&self) -> Result<(), Error> {
+        let garbage = retention::collect(&self.cacher).await?;
+        if !garbage.is_empty() {
+            let freed = retention::remove(&garbage).await?;
+            let message = format!(
+                "Removed {} old files and folders of the cache, freed {}",
+                garbage.len(),
+                disk::format_size(freed)
+            );
+            println!("{}", message.dimmed());
+        }
+        Ok(())
+    }
+
+    /// Picks the version of the app to launch in the folder.
+    ///
+    /// A version required by the project is used for this launch only
+    /// and doesn't change the version used by default.
+    async fn resolve_version(&mut self, settings: &Settings) -> Result<Version, Error> {
+        match &settings.version {
+            Some(req) => self.ensure_version(&req.value).await,
+            None => self.cacher.ri_learn.get_version(),
+        }
+    }
+
+    /// Installs a version of the app required by the project
+    async fn ensure_version(&mut self, req: &VersionReq) -> Result<Version, Error> {
+        let policy = self.cacher.policy();
+        let suitable = |ver: &Version| req.matches(ver) && policy.allows(ver);
+        if let Some(version) = self.cacher.ri_learn.version.as_ref() {
+            if suitable(version) {
+                return Ok(version.clone());
+            }
+        }
+        println!("The folder requires the app version {req}");
+        let _lock = self.cacher.lock(LockMode::Exclusive).await?;
+        let available = self.cacher.available_versions(&app_info::LEARN).await?;
+        if let Some(version) = available.into_iter().rev().find(|ver| suitable(ver)) {
+            println!("Using the installed version {version}");
+            return Ok(version);
+        }
+        let release = self
+            .github_api
+            .matching_release(&app_info::LEARN, req)
+            .await?;
+        self.install_ri_learn(release).await
+    }
+
     /*
     async fn update_ri_stack(&mut self, force: bool) -> Result<(), Error> {
-        if self.cacher.ri_stack.is_update_required() || force {
+        let interval = self.cacher.config().preferences.update_interval();
+        if self.cacher.ri_stack.is_update_required(interval) || force {
             println!("Checking an update for the stack...");
 
             let latest = self.github_api.latest_release(&app_info::STACK).await?;
@@ -136,47 +393,214 @@ impl App {
     }
     */
 
-    fn start_app(&mut self, app_info: &AppInfo, args: Vec<String>) -> Result<(), Error> {
-        let mut bin_path = self.cacher.bin_dir().clone();
-        bin_path.push(app_info.name);
-        let child = Command::new(bin_path)
+    fn start_app(
+        &mut self,
+        app_info: &AppInfo,
+        version: &Version,
+        args: Vec<String>,
+        workdir: &Path,
+        env: &BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        let bin_path = self.cacher.app_path(app_info, version);
+        let mut command = std::process::Command::new(bin_path);
+        // The own group allows to stop the app with all its subprocesses
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let filter = &self.cacher.config().global.env_filter;
+        if filter.is_active() {
+            command
+                .env_clear()
+                .envs(environment::child_env(filter, env));
+        } else {
+            command.envs(env);
+        }
+        let mut child = Command::from(command)
             .args(args)
+            .current_dir(workdir)
             .kill_on_drop(true)
             .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()?;
+        if let Some(output) = self.output.as_ref() {
+            output.tail.clear();
+            if let Some(stdout) = child.stdout.take() {
+                self.readers.push(output.spawn(stdout, Stream::Stdout));
+            }
+            if let Some(stderr) = child.stderr.take() {
+                self.readers.push(output.spawn(stderr, Stream::Stderr));
+            }
+        }
         self.app = Some(child);
         Ok(())
     }
 
-    async fn closed(&mut self) -> Result<(), Error> {
-        if let Some(child) = self.app.as_mut() {
-            child.wait().await?;
+    async fn register_instance(
+        &self,
+        port: u16,
+        workdir: PathBuf,
+        version: Version,
+        options: LaunchOptions,
+    ) -> Result<Instance, Error> {
+        let pid = self
+            .app
+            .as_ref()
+            .and_then(Child::id)
+            .ok_or_else(|| Error::msg("App is not sarted"))?;
+        let session = self.output.as_ref().map(|output| output.log.id);
+        self.registry
+            .register(pid, port, workdir, Some(version), options, session)
+            .await
+    }
+
+    /// Takes the first port that is not used by other instances.
+    ///
+    /// Ports taken by instances of other profiles or other programs are skipped.
+    async fn allocate_port(&self) -> Result<u16, Error> {
+        let instances = self.registry.list().await?;
+        let ports = (PORT..PORT + MAX_INSTANCES)
+            .filter(|port| instances.iter().all(|instance| instance.port != *port));
+        for port in ports {
+            let url = format!("http://localhost:{port}/");
+            if self.probe_tool.is_free(&url).await {
+                return Ok(port);
+            }
+        }
+        Err(err!(
+            "No free ports from {PORT} to {}, stop some instances of the app",
+            PORT + MAX_INSTANCES - 1
+        ))
+    }
+
+    /// Checks the port set explicitly can be used.
+    ///
+    /// Returns `None` if the port is taken by an instance of the app
+    /// and the user decided not to start a new one.
+    async fn check_port(&mut self, port: u16) -> Result<Option<u16>, Error> {
+        let instances = self.registry.list().await?;
+        if let Some(instance) = instances.into_iter().find(|inst| inst.port == port) {
+            let folder = instance.workdir.display();
+            println!(
+                "The port {port} is used by the instance {} in {folder}",
+                instance.id
+            );
+            if !self.attach_to_running(instance).await? {
+                return Ok(None);
+            }
+        }
+        let url = format!("http://localhost:{port}/");
+        if !self.probe_tool.is_free(&url).await {
+            return Err(match process::port_owner(port) {
+                Some(owner) => err!(
+                    "The port is not free: {url} (used by '{}' with pid {})",
+                    owner.name,
+                    owner.pid
+                ),
+                None => err!("The port is not free: {url}"),
+            });
+        }
+        Ok(Some(port))
+    }
+
+    async fn stop_instance(&self, instance: &Instance) -> Result<(), Error> {
+        // The process could exit since the registry was read
+        if !instance.is_running() {
+            return self.registry.unregister(instance).await;
+        }
+        self.registry.request_stop(instance).await?;
+        let grace_period = self.cacher.config().global.grace_period();
+        process::terminate(instance.pid, grace_period).await?;
+        self.registry.unregister(instance).await
+    }
+
+    /// Handles the case when the app is already running in the folder.
+    ///
+    /// Returns `true` if a new instance of the app has to be started.
+    async fn attach_to_running(&mut self, instance: Instance) -> Result<bool, Error> {
+        let url = instance.url();
+        let link = url.green();
+        let version = instance
+            .version
+            .as_ref()
+            .map(|ver| format!("v{ver} "))
+            .unwrap_or_default();
+        println!("The app {version}is already running at: {link}");
+        if !self.probe_tool.is_healthy(&url).await {
+            let warn = "The app doesn't respond, consider restarting it.".yellow();
+            println!("{warn}");
+        }
+        if !std::io::stdin().is_terminal() {
+            let note = format!("Stop it with `knowledge stop {}` first", instance.id);
+            println!("{}", note.dimmed());
+            return Ok(false);
+        }
+        let choices = ["Open in browser", "Restart", "Stop", "Cancel"];
+        let choice = Select::new()
+            .with_prompt("What do you want to do?")
+            .items(&choices)
+            .default(0)
+            .interact()?;
+        match choice {
+            0 => {
+                webbrowser::open(&url).ok();
+                Ok(false)
+            }
+            1 => {
+                println!("Restarting the app...");
+                self.stop_instance(&instance).await?;
+                Ok(true)
+            }
+            2 => {
+                println!("Stopping the app...");
+                self.stop_instance(&instance).await?;
+                println!("Done");
+                Ok(false)
+            }
+            _ => Ok(false),
         }
-        Ok(())
     }
 
-    async fn terminate_app(&mut self) -> Result<(), Error> {
+    /// Forwards the signal to the app and waits for the grace period.
+    ///
+    /// The second `Ctrl-C` or the end of the period kills the whole group.
+    async fn terminate_app(&mut self, shutdown: Shutdown) -> Result<(), Error> {
         let mut child = self
             .app
             .take()
             .ok_or_else(|| Error::msg("App is not sarted"))?;
-        child.kill().await?;
+        let Some(pid) = child.id() else {
+            // Already exited
+            child.wait().await?;
+            return Ok(());
+        };
+        process::forward(pid, shutdown)?;
+        let grace_period = self.cacher.config().global.grace_period();
+        select! {
+            res = timeout(grace_period, child.wait()) => {
+                if res.is_err() {
+                    println!("The app didn't stop in time. Killing it.");
+                }
+            }
+            _ = signal::ctrl_c() => {
+                println!("Forcing termination.");
+            }
+        }
+        // Kills subprocesses left by the app
+        process::kill_group(pid)?;
         child.wait().await?;
         Ok(())
     }
 
-    fn show_banner(&self, logo: &AppInfo, app_state: &AppState) -> Result<(), Error> {
+    /// Shows the version of the app that is launched, it may be required by the project
+    fn show_banner(&self, logo: &AppInfo, app_ver: &Version) -> Result<(), Error> {
         let launcher_ver = self.cacher.launcher.get_version()?;
-        let app_ver = app_state.get_version()?;
         let Color(r, g, b) = logo.bg;
         let ri = logo.line_1.bold().white().on_truecolor(r, g, b);
         println!("{ri} v{app_ver} (product)");
         let name = logo.line_2.bold().white().on_truecolor(r, g, b);
         let launcher_info = format!("v{launcher_ver} (launcher)").truecolor(100, 100, 100);
         println!("{name} {launcher_info}");
-        println!("");
+        println!();
         Ok(())
     }
 
@@ -185,74 +609,1164 @@ impl App {
         force: bool,
         opts: Option<UpdateCommand>,
     ) -> Result<(), Error> {
-        if let Err(err) = self.update_launcher(force).await {
-            let err = err.to_string().red();
-            println!("Launcher updating failed: {err}");
-        }
-        if let Err(err) = self.update_ri_learn(force, opts).await {
-            let err = err.to_string().red();
-            println!("App updating failed: {err}");
+        // Nothing to check without the network
+        if !self.http.is_offline() {
+            if let Err(err) = self.update_launcher(force).await {
+                // The lack of the network is reported once below
+                if !err.is::<OfflineError>() {
+                    let err = err.to_string().red();
+                    println!("Launcher updating failed: {err}");
+                }
+            }
+            if let Err(err) = self.update_ri_learn(force, opts).await {
+                if !err.is::<OfflineError>() {
+                    let err = err.to_string().red();
+                    println!("App updating failed: {err}");
+                }
+            }
+            /*
+            if let Err(err) = self.update_ri_stack(force).await {
+                let err = err.to_string().red();
+                println!("Stack updating failed: {err}");
+            }
+            */
         }
-        /*
-        if let Err(err) = self.update_ri_stack(force).await {
-            let err = err.to_string().red();
-            println!("Stack updating failed: {err}");
+        if self.http.is_offline() {
+            if self.cacher.ri_learn.is_not_exist() {
+                self.use_installed_version().await?;
+            }
+            let Some(version) = &self.cacher.ri_learn.version else {
+                let os = self.cacher.system.as_ref();
+                let asset = github::asset_name(&app_info::LEARN, &"<version>", os);
+                return Err(err!(
+                    "The app is not installed and the network is not available.\n\
+                     Download {asset} from the releases of the app on another computer \
+                     and install it with `knowledge import <path>`"
+                ));
+            };
+            let note = format!("Working offline with the installed version {version}");
+            println!("{}", note.dimmed());
         }
-        */
         Ok(())
     }
 
+    /// Picks the newest allowed version that is installed, but not used yet.
+    ///
+    /// E.g. the version installed for all users, or the one left by `clean --state`.
+    async fn use_installed_version(&mut self) -> Result<(), Error> {
+        let available = self.cacher.available_versions(&app_info::LEARN).await?;
+        let policy = self.cacher.policy();
+        let Some(version) = available.into_iter().rev().find(|ver| policy.allows(ver)) else {
+            return Ok(());
+        };
+        println!("Using the installed version {version}");
+        self.cacher
+            .update_state(|state| {
+                state.ri_learn.version.get_or_insert(version);
+            })
+            .await
+    }
+
     /*
     pub async fn command_stack(&mut self) -> Result<(), Error> {
-        self.show_banner(&app_info::STACK, &self.cacher.ri_stack)?;
+        let version = self.cacher.ri_stack.get_version()?;
+        self.show_banner(&app_info::STACK, &version)?;
         Ok(())
     }
     */
 
-    pub async fn command_learn(&mut self, ride: bool) -> Result<(), Error> {
-        self.show_banner(&app_info::LEARN, &self.cacher.ri_learn)?;
+    pub async fn command_learn(&mut self, opts: LearnCommand, ride: bool) -> Result<(), Error> {
+        let Some(workdir) = self.select_workdir(opts.dir.as_deref()).await? else {
+            return Ok(());
+        };
+        let settings = self.resolve_settings(&opts, &workdir).await?;
+        let version = self.resolve_version(&settings).await?;
+        self.show_banner(&app_info::LEARN, &version)?;
+        if self.cacher.config().global.snapshots.auto {
+            self.auto_snapshot(&workdir).await;
+        }
+        if let Some(instance) = self.registry.find_by_workdir(&workdir).await? {
+            if !self.attach_to_running(instance).await? {
+                return Ok(());
+            }
+        }
+        let port = match &settings.port {
+            Some(port) => match self.check_port(port.value).await? {
+                Some(port) => port,
+                None => return Ok(()),
+            },
+            None => self.allocate_port().await?,
+        };
+        self.launch(ride, opts, settings, version, workdir, port)
+            .await
+    }
+
+    /// Saves the folder before the practice, a failure doesn't stop the launch
+    async fn auto_snapshot(&self, workdir: &Path) {
+        if workdir.parent().is_none() || dirs::home_dir().as_deref() == Some(workdir) {
+            let note = "Snapshots are not taken of the home or the root folder";
+            println!("{}", note.dimmed());
+            return;
+        }
+        let config = &self.cacher.config().global.snapshots;
+        match self.snapshot_store.create(workdir, true, config).await {
+            Ok(Some(info)) => {
+                let message = format!("Saved the snapshot {} of the working folder", info.id);
+                println!("{}", message.dimmed());
+            }
+            Ok(None) => {}
+            Err(err) => {
+                let warn = format!("Can't take a snapshot of the working folder: {err}").yellow();
+                println!("{warn}");
+            }
+        }
+    }
 
-        let url = "http://localhost:6361/";
-        self.probe_tool.probe_is_free(url).await?;
+    /// Merges settings of the user, the project and the command line
+    async fn resolve_settings(
+        &self,
+        opts: &LearnCommand,
+        workdir: &Path,
+    ) -> Result<Settings, Error> {
+        let mut settings = Settings::default();
+        let config = self.cacher.config();
+        let port = config.preferences.port;
+        settings.add_user(self.cacher.config_path(), port, &config.env);
+        let project = Project::discover(workdir).await?;
+        if let Some(project) = &project {
+            settings.add_project(project);
+        }
+        settings.add_command_line(opts.port, &opts.args, &opts.envs);
+        let lines = settings.describe();
+        if !lines.is_empty() {
+            println!("Effective settings:");
+            for line in lines {
+                println!("  {}", line.dimmed());
+            }
+        }
+        if let Some(course) = &settings.course {
+            let course = course.value.green();
+            println!("Course: {course}");
+        }
+        Ok(settings)
+    }
 
+    /// Picks the folder and checks it's suitable for the practice.
+    ///
+    /// Returns `None` if the user declined to use the folder.
+    async fn select_workdir(&mut self, dir: Option<&Path>) -> Result<Option<PathBuf>, Error> {
+        let interactive = std::io::stdin().is_terminal();
+        let folder = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => {
+                let current = std::env::current_dir()?;
+                let recent: Vec<PathBuf> = self
+                    .cacher
+                    .history
+                    .existing()
+                    .into_iter()
+                    .filter(|folder| folder != &current)
+                    .collect();
+                if interactive && !recent.is_empty() {
+                    let mut items = vec![format!("{} (current)", current.display())];
+                    items.extend(recent.iter().map(|folder| folder.display().to_string()));
+                    let choice = Select::new()
+                        .with_prompt("Choose a working folder")
+                        .items(&items)
+                        .default(0)
+                        .interact()?;
+                    match choice {
+                        0 => current,
+                        n => recent[n - 1].clone(),
+                    }
+                } else {
+                    current
+                }
+            }
+        };
+        let (folder, created) = workspace::ensure(&folder).await?;
+        if created {
+            let path = folder.display().to_string().green();
+            println!("Created a new working folder: {path}");
+        }
+        if let Some(reason) = workspace::warning(&folder) {
+            let warn = format!("The working folder is not suitable: {reason}.").yellow();
+            println!("{warn}");
+            if interactive
+                && !Confirm::new()
+                    .with_prompt("Start the app there anyway?")
+                    .default(false)
+                    .interact()?
+            {
+                return Ok(None);
+            }
+        }
+        self.cacher
+            .update_state(|state| state.history.touch(&folder))
+            .await?;
+        Ok(Some(folder))
+    }
+
+    async fn launch(
+        &mut self,
+        ride: bool,
+        opts: LearnCommand,
+        settings: Settings,
+        version: Version,
+        workdir: PathBuf,
+        port: u16,
+    ) -> Result<(), Error> {
+        let url = format!("http://localhost:{port}/");
         let link = url.green(); //.truecolor(255, 61, 0);
         println!("The app is started and active at: {link}");
         println!("Keep this terminal active to use the app.");
-        println!("");
+        println!();
 
-        let workdir = std::env::current_dir()?;
-        let workdir = workdir.display().to_string().green();
-        println!("Working folder is: {workdir}");
+        let folder = workdir.display().to_string().green();
+        println!("Working folder is: {folder}");
 
         let mut args = Vec::new();
         if ride {
             args.push("--ride".into());
         }
-        self.start_app(&app_info::LEARN, args)?;
-        // TODO: Get a port value from the app
-        // or create it dinamically here and set to the app
-        self.probe_tool.probe(url).await?;
-        webbrowser::open(url).ok();
+        if port != PORT {
+            args.push("--port".into());
+            args.push(port.to_string());
+        }
+        args.extend(settings.args());
+        let env = settings.env();
+        let instances = self.registry.list().await?;
+        let active: Vec<u32> = instances.iter().filter_map(|inst| inst.session).collect();
+        let log = self
+            .log_store
+            .create_session(&self.cacher.config().global.logs, &active)
+            .await?;
+        self.output = Some(OutputPump {
+            log,
+            echo: opts.attach,
+            tail: OutputTail::default(),
+        });
+
+        let config = &self.cacher.config().global.supervisor;
+        let auto_restart = opts.restart || config.auto_restart;
+        let health_interval = Duration::from_secs(config.health_interval);
+        let health_failures = config.health_failures;
+        let mut crashes = CrashTracker::new(config);
+        let mut first_start = true;
+        // Signals are caught before the start to not leave the app orphaned
+        let mut listener = ShutdownListener::new()?;
+        loop {
+            // Another launcher can't replace binaries while the app is starting
+            let lock = self.cacher.lock(LockMode::Shared).await?;
+            self.start_app(&app_info::LEARN, &version, args.clone(), &workdir, &env)?;
+            drop(lock);
+            let instance = self
+                .register_instance(port, workdir.clone(), version.clone(), (&opts).into())
+                .await?;
+            let note = format!(
+                "Started v{version} with pid {} in {}",
+                instance.pid,
+                workdir.display()
+            );
+            self.log_note(&note).await;
+            let child = self
+                .app
+                .as_mut()
+                .ok_or_else(|| Error::msg("App is not sarted"))?;
+            let probe_tool = &self.probe_tool;
+            // The app can crash right after the start
+            let started = select! {
+                shutdown = listener.recv() => Some(AppExit::Shutdown(shutdown)),
+                status = child.wait() => Some(AppExit::Exited(status?)),
+                res = probe_tool.probe(&url) => res.map(|_| None)?,
+            };
+            let exit = match started {
+                Some(exit) => exit,
+                None => {
+                    if first_start {
+                        if self.cacher.config().preferences.open_browser {
+                            webbrowser::open(&url).ok();
+                        }
+                        first_start = false;
+                    }
+                    let child = self
+                        .app
+                        .as_mut()
+                        .ok_or_else(|| Error::msg("App is not sarted"))?;
+                    let probe_tool = &self.probe_tool;
+                    select! {
+                        shutdown = listener.recv() => AppExit::Shutdown(shutdown),
+                        status = child.wait() => AppExit::Exited(status?),
+                        _ = probe_tool.watch(&url, health_interval, health_failures) => AppExit::Hung,
+                    }
+                }
+            };
+            let reason = match exit {
+                AppExit::Shutdown(shutdown) => {
+                    println!("Terminating the app. Press Ctrl-C again to force.");
+                    self.log_note(&format!("Terminating by {shutdown:?}")).await;
+                    self.terminate_app(shutdown).await?;
+                    self.registry.unregister(&instance).await?;
+                    self.finish_output().await;
+                    return Ok(());
+                }
+                AppExit::Exited(status) if status.success() => {
+                    self.app.take();
+                    println!("App was closed. Done.");
+                    self.log_note("The app was closed").await;
+                    self.registry.unregister(&instance).await?;
+                    self.finish_output().await;
+                    return Ok(());
+                }
+                AppExit::Exited(_) if self.registry.is_stop_requested(&instance).await? => {
+                    self.app.take();
+                    println!("The app was stopped. Done.");
+                    self.log_note("The app was stopped by another process")
+                        .await;
+                    self.registry.unregister(&instance).await?;
+                    self.finish_output().await;
+                    return Ok(());
+                }
+                AppExit::Exited(status) => {
+                    self.app.take();
+                    format!("The app {}", supervisor::describe(&status))
+                }
+                AppExit::Hung => {
+                    let mut child = self
+                        .app
+                        .take()
+                        .ok_or_else(|| Error::msg("App is not sarted"))?;
+                    if let Some(pid) = child.id() {
+                        process::kill_group(pid)?;
+                    }
+                    child.wait().await?;
+                    "The app stopped responding".to_string()
+                }
+            };
+            self.registry.unregister(&instance).await?;
+            self.log_note(&reason).await;
+            println!("{}", reason.red());
+            self.report_crash().await;
+            if !auto_restart {
+                return Err(Error::msg(reason));
+            }
+            if !crashes.record() {
+                let window = crashes.window().as_secs();
+                return Err(err!(
+                    "The app crashed {} times within {window} seconds, giving up",
+                    crashes.recent()
+                ));
+            }
+            let backoff = crashes.backoff();
+            println!("Restarting the app in {} seconds...", backoff.as_secs());
+            select! {
+                _ = sleep(backoff) => {}
+                _ = listener.recv() => {
+                    self.finish_output().await;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Prints the last lines the app wrote to `stderr`
+    async fn report_crash(&mut self) {
+        self.finish_output().await;
+        let Some(output) = self.output.as_ref() else {
+            return;
+        };
+        let lines = output.tail.lines();
+        if !lines.is_empty() {
+            println!("The last output of the app:");
+            for line in lines {
+                println!("  {}", line.dimmed());
+            }
+        }
+        let path = output.log.path().await.display().to_string().green();
+        println!("The full log is here: {path}");
+    }
+
+    /// Waits a bit for the rest of the output of the exited app.
+    ///
+    /// The streams may be kept open by subprocesses of the app,
+    /// that's why it doesn't wait for the end of them.
+    async fn finish_output(&mut self) {
+        for reader in self.readers.drain(..) {
+            timeout(OUTPUT_TIMEOUT, reader).await.ok();
+        }
+    }
+
+    async fn log_note(&self, message: &str) {
+        if let Some(output) = self.output.as_ref() {
+            output.log.note(message).await.ok();
+        }
+    }
+
+    pub async fn command_init(&mut self, opts: InitCommand, ride: bool) -> Result<(), Error> {
+        let dir = opts
+            .dir
+            .clone()
+            .or_else(|| opts.course.as_ref().map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("."));
+        if workspace::is_initialized(&dir).await? {
+            return Err(err!(
+                "The folder already contains a workspace: {}",
+                dir.display()
+            ));
+        }
+        let template = match &opts.course {
+            Some(course) => Some(self.fetch_template(course).await?),
+            None => None,
+        };
+        let (dir, _) = workspace::ensure(&dir).await?;
+        if let Some(template) = template {
+            println!("Unpacking the template...");
+            workspace::unpack_template(template, &dir)?;
+        }
+        workspace::scaffold(&dir, opts.course.as_deref()).await?;
+        let path = dir.display().to_string().green();
+        println!("The workspace is ready: {path}");
+
+        let launch = opts.launch
+            || std::io::stdin().is_terminal()
+                && Confirm::new()
+                    .with_prompt("Do you want to launch the app there?")
+                    .default(true)
+                    .interact()?;
+        if launch {
+            self.command_update(false, None).await?;
+            let learn = LearnCommand {
+                dir: Some(dir),
+                ..LearnCommand::default()
+            };
+            self.command_learn(learn, ride).await?;
+        }
+        Ok(())
+    }
+
+    /// Takes the template of the latest release from the cache or downloads it.
+    ///
+    /// Without the network the newest template in the cache is used.
+    async fn fetch_template(&mut self, course: &str) -> Result<std::fs::File, Error> {
+        let release = match self.github_api.latest_release(&app_info::LEARN).await {
+            Ok(release) => release,
+            Err(err) => {
+                let cached = self
+                    .cacher
+                    .cached_templates(&app_info::LEARN, course)
+                    .await?;
+                let Some((version, path)) = cached.into_iter().last() else {
+                    return Err(err.context(format!(
+                        "The template of '{course}' is not available, \
+                         import it with `knowledge import <path>`"
+                    )));
+                };
+                let note = format!("Using the template of '{course}' from the release {version}");
+                println!("{}", note.dimmed());
+                return Ok(std::fs::File::open(path)?);
+            }
+        };
+        let path = self
+            .cacher
+            .template_path(&app_info::LEARN, course, &release.version);
+        if !fs::try_exists(&path).await? {
+            println!("Downloading the template of '{course}'...");
+            let name = github::template_name(&app_info::LEARN, course);
+            let url = release
+                .get_asset(&name)
+                .ok_or_else(|| err!("The course '{course}' has no template"))?;
+            let archive = self.github_api.download_assets(url).await?;
+            self.save_template(course, &release.version, archive)
+                .await?;
+        }
+        Ok(std::fs::File::open(path)?)
+    }
+
+    /// Stores the template of the release and drops templates of older releases
+    async fn save_template(
+        &self,
+        course: &str,
+        version: &Version,
+        mut archive: fs::File,
+    ) -> Result<(), Error> {
+        let path = self.cacher.template_path(&app_info::LEARN, course, version);
+        let partial = path.with_extension("part");
+        let mut file = fs::File::create(&partial).await?;
+        tokio::io::copy(&mut archive, &mut file).await?;
+        fs::rename(&partial, &path).await?;
+        let cached = self
+            .cacher
+            .cached_templates(&app_info::LEARN, course)
+            .await?;
+        for (_, old) in cached.into_iter().filter(|(ver, _)| ver < version) {
+            disk::remove(&old).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn command_ps(&mut self) -> Result<(), Error> {
+        let instances = self.registry.list().await?;
+        if instances.is_empty() {
+            println!("No running instances");
+            return Ok(());
+        }
+        let header = format!(
+            "{:<4} {:<8} {:<6} {:<10} {:<20} {}",
+            "ID", "PID", "PORT", "VERSION", "STARTED", "FOLDER"
+        );
+        println!("{}", header.bold());
+        for instance in instances {
+            let version = instance
+                .version
+                .as_ref()
+                .map(Version::to_string)
+                .unwrap_or_else(|| "-".into());
+            let started = instance
+                .started
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S");
+            println!(
+                "{:<4} {:<8} {:<6} {:<10} {:<20} {}",
+                instance.id,
+                instance.pid,
+                instance.port,
+                version,
+                started,
+                instance.workdir.display()
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn command_stop(&mut self, opts: StopCommand) -> Result<(), Error> {
+        let instances = if let Some(id) = opts.id {
+            vec![self.registry.get(id).await?]
+        } else {
+            self.registry.list().await?
+        };
+        for instance in instances {
+            println!("Stopping the instance {}...", instance.id);
+            self.stop_instance(&instance).await?;
+        }
+        println!("Done");
+        Ok(())
+    }
+
+    pub async fn command_restart(&mut self, opts: RestartCommand, ride: bool) -> Result<(), Error> {
+        let instance = self.registry.get(opts.id).await?;
+        println!("Stopping the instance {}...", instance.id);
+        self.stop_instance(&instance).await?;
+        let opts = instance.options.to_command(instance.workdir.clone());
+        let settings = self.resolve_settings(&opts, &instance.workdir).await?;
+        let version = self.resolve_version(&settings).await?;
+        self.show_banner(&app_info::LEARN, &version)?;
+        self.launch(
+            ride,
+            opts,
+            settings,
+            version,
+            instance.workdir,
+            instance.port,
+        )
+        .await
+    }
+
+    pub async fn command_logs(&mut self, opts: LogsCommand) -> Result<(), Error> {
         select! {
-            _ = signal::ctrl_c() => {
-                println!("Terminating the app.");
-                self.terminate_app().await?;
+            res = logs::show(&self.log_store, opts.session, opts.follow) => res,
+            _ = signal::ctrl_c() => Ok(()),
+        }
+    }
+
+    pub async fn command_snapshot(&mut self, opts: SnapshotCommand) -> Result<(), Error> {
+        let dir = match opts.dir {
+            Some(dir) => dir,
+            None => std::env::current_dir()?,
+        };
+        let workdir = fs::canonicalize(&dir)
+            .await
+            .map_err(|e| err!("Can't open the folder {}: {e}", dir.display()))?;
+        let store = &self.snapshot_store;
+        let config = &self.cacher.config().global.snapshots;
+        match opts.action {
+            SnapshotAction::List => {
+                let snapshots = store.list(&workdir).await?;
+                if snapshots.is_empty() {
+                    println!("No snapshots of {}", workdir.display());
+                    return Ok(());
+                }
+                let header = format!(
+                    "{:<4} {:<20} {:<6} {:<10} {}",
+                    "ID", "CREATED", "FILES", "SIZE", "KIND"
+                );
+                println!("{}", header.bold());
+                for info in snapshots {
+                    let created = info
+                        .created
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S");
+                    let size = disk::format_size(info.size);
+                    let kind = if info.auto { "auto" } else { "manual" };
+                    println!(
+                        "{:<4} {:<20} {:<6} {:<10} {}",
+                        info.id, created, info.files, size, kind
+                    );
+                }
+            }
+            SnapshotAction::Create => match store.create(&workdir, false, config).await? {
+                Some(info) => {
+                    let id = info.id.to_string().green();
+                    println!("Saved the snapshot {id} ({} files)", info.files);
+                }
+                None => println!("No changes since the latest snapshot"),
+            },
+            SnapshotAction::Diff(diff) => {
+                let changes = store.diff(&workdir, diff.id).await?;
+                if changes.is_empty() {
+                    println!("No changes since the snapshot {}", diff.id);
+                }
+                for change in changes {
+                    match change {
+                        Change::Added(path) => println!("{}", format!("+ {path}").green()),
+                        Change::Removed(path) => println!("{}", format!("- {path}").red()),
+                        Change::Modified(path) => println!("{}", format!("~ {path}").yellow()),
+                    }
+                }
+            }
+            SnapshotAction::Restore(restore) => {
+                let changes = store.diff(&workdir, restore.id).await?;
+                if changes.is_empty() {
+                    println!("The folder is the same as the snapshot {}", restore.id);
+                    return Ok(());
+                }
+                println!("{} files will be changed", changes.len());
+                if !restore.yes
+                    && !Confirm::new()
+                        .with_prompt(format!("Restore the snapshot {}?", restore.id))
+                        .default(false)
+                        .interact()?
+                {
+                    return Ok(());
+                }
+                if let Some(info) = store.restore(&workdir, restore.id, config).await? {
+                    println!("Saved the previous state as the snapshot {}", info.id);
+                }
+                println!("Restored the snapshot {}", restore.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removing files of the launcher can be disabled by the organisation
+    fn check_clean_allowed(&self, what: &str) -> Result<(), Error> {
+        if !self.cacher.policy().allow_clean {
+            return Err(err!(
+                "{what} is disabled by the policy {}",
+                self.cacher.policy_path().display()
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn command_clean(&mut self, opts: CleanCommand) -> Result<(), Error> {
+        self.check_clean_allowed("Cleaning")?;
+        let _lock = self.cacher.lock(LockMode::Exclusive).await?;
+        self.cacher.reload_state().await?;
+        let whole_cache = !(opts.binaries || opts.downloads || opts.old_versions || opts.state);
+        let remove_binaries = opts.binaries || whole_cache;
+        let mut paths = Vec::new();
+        if remove_binaries {
+            paths.push(self.cacher.bin_dir().clone());
+        } else if opts.old_versions {
+            paths.extend(self.cacher.old_binaries().await?);
+        }
+        if opts.downloads || whole_cache {
+            paths.push(self.cacher.templates_dir().clone());
+        }
+        if opts.state {
+            paths.push(self.cacher.state_path().clone());
+            paths.push(self.cacher.logs_dir().clone());
+        }
+        let Some(paths) = confirm_removal(paths, opts.dry_run, opts.yes).await? else {
+            return Ok(());
+        };
+        for path in &paths {
+            disk::remove(path).await?;
+        }
+        // The removed state is not written back, the next run starts from scratch
+        if remove_binaries && !opts.state {
+            self.cacher.ri_learn.reset();
+            self.cacher.write_state().await?;
+        }
+        println!("Done");
+        Ok(())
+    }
+
+    pub async fn command_cache(&mut self, opts: CacheCommand) -> Result<(), Error> {
+        match opts.action {
+            CacheAction::Info => {
+                let cache_dir = self.cacher.cache_dir();
+                let total = disk::format_size(disk::size_of(cache_dir).await?);
+                println!("Cache: {} ({})", cache_dir.display(), total.green());
+                let retention = &self.cacher.config().global.retention;
+                if let Some(max_size) = retention.max_cache_size {
+                    println!("Limit: {}", disk::format_size(max_size));
+                }
+                println!("{}", app_info::LEARN.name.bold());
+                let installed = self.cacher.installed_versions(&app_info::LEARN).await?;
+                for version in installed.iter().rev() {
+                    let path = self.cacher.version_dir(&app_info::LEARN, version);
+                    let size = disk::format_size(disk::size_of(&path).await?);
+                    let mut marks = Vec::new();
+                    if self.cacher.ri_learn.version.as_ref() == Some(version) {
+                        marks.push("current");
+                    }
+                    if retention.pinned.contains(version) {
+                        marks.push("pinned");
+                    }
+                    let line = format!("  {:<12} {:>10}  {}", version, size, marks.join(", "));
+                    println!("{}", line.trim_end());
+                }
+                let shared = self.cacher.shared_versions(&app_info::LEARN).await?;
+                if !shared.is_empty() {
+                    let shared: Vec<String> = shared.iter().rev().map(Version::to_string).collect();
+                    let line = format!("  Installed for all users: {}", shared.join(", "));
+                    println!("{}", line.dimmed());
+                }
+                let folders = [
+                    ("Templates", self.cacher.templates_dir()),
+                    ("Logs", self.cacher.logs_dir()),
+                    ("Snapshots", self.cacher.snapshots_dir()),
+                ];
+                for (name, path) in folders {
+                    let size = disk::format_size(disk::size_of(path).await?);
+                    println!("{:<14} {:>10}  {}", name.bold(), size, path.display());
+                }
+            }
+            CacheAction::Gc(gc) => {
+                self.check_clean_allowed("Collecting garbage")?;
+                let _lock = self.cacher.lock(LockMode::Exclusive).await?;
+                self.cacher.reload_state().await?;
+                let garbage = retention::collect(&self.cacher).await?;
+                if garbage.is_empty() {
+                    println!("Nothing to remove");
+                    return Ok(());
+                }
+                let total: u64 = garbage.iter().map(|item| item.size).sum();
+                let title = if gc.dry_run {
+                    "The following would be removed:"
+                } else {
+                    "Removing:"
+                };
+                println!("{title}");
+                for item in &garbage {
+                    let size = disk::format_size(item.size);
+                    println!("  {:>10}  {}", size, item.path.display());
+                }
+                if !gc.dry_run {
+                    retention::remove(&garbage).await?;
+                    println!("Freed {}", disk::format_size(total).green());
+                }
             }
-            _ = self.closed() => {
-                println!("App was closed. Done.");
+        }
+        Ok(())
+    }
+
+    pub async fn command_config(&mut self, opts: ConfigCommand) -> Result<(), Error> {
+        let path = self.cacher.config_path().clone();
+        let policy = self.cacher.policy();
+        let check_unlocked = |key: &str| {
+            if policy.is_locked(key) {
+                let policy_path = self.cacher.policy_path().display();
+                Err(err!("{key} is locked by the policy {policy_path}"))
+            } else {
+                Ok(())
+            }
+        };
+        match opts.action {
+            ConfigAction::Get { key } => match preferences::get(self.cacher.config(), &key)? {
+                Some(value) => println!("{value}"),
+                None => println!("{}", "(not set)".dimmed()),
+            },
+            ConfigAction::Set { key, value } => {
+                check_unlocked(&key)?;
+                let mut doc: DocumentMut = fs::read_to_string(&path).await?.parse()?;
+                preferences::set(&mut doc, &key, &value)?;
+                disk::write_atomic(&path, &doc.to_string()).await?;
+                println!("{key} = {}", value.green());
+            }
+            ConfigAction::Unset { key } => {
+                check_unlocked(&key)?;
+                let mut doc: DocumentMut = fs::read_to_string(&path).await?.parse()?;
+                if preferences::unset(&mut doc, &key)? {
+                    disk::write_atomic(&path, &doc.to_string()).await?;
+                    println!("{key} is reset to the default");
+                } else {
+                    println!("{key} is not set");
+                }
+            }
+            ConfigAction::List => {
+                let config = self.cacher.config();
+                println!("{}", path.display().to_string().dimmed());
+                let env_keys = config.env.keys().map(|name| format!("env.{name}"));
+                for key in preferences::keys()?.into_iter().chain(env_keys) {
+                    let line = match preferences::get(config, &key)? {
+                        Some(_) if preferences::is_secret(&key) => {
+                            format!("{key} = {}", "(hidden)".dimmed())
+                        }
+                        Some(value) => format!("{key} = {value}"),
+                        None => format!("{key} {}", "(not set)".dimmed()),
+                    };
+                    if policy.is_locked(&key) {
+                        println!("{line} {}", "(locked by policy)".yellow());
+                    } else {
+                        println!("{line}");
+                    }
+                }
+            }
+            ConfigAction::Edit => {
+                let editor = std::env::var("VISUAL")
+                    .or_else(|_| std::env::var("EDITOR"))
+                    .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.into());
+                // The editor may have arguments, e.g. `code --wait`
+                let mut parts = editor.split_whitespace();
+                let program = parts.next().unwrap_or("vi");
+                let status = Command::new(program)
+                    .args(parts)
+                    .arg(&path)
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(err!("The editor {editor} failed: {status}"));
+                }
+                let doc: DocumentMut = fs::read_to_string(&path).await?.parse()?;
+                if let Err(err) = preferences::validate(&doc) {
+                    let warn = format!("The config is not valid: {err}").yellow();
+                    println!("{warn}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn command_uninstall(&mut self, opts: UninstallCommand) -> Result<(), Error> {
+        self.check_clean_allowed("Uninstalling")?;
+        if !self.registry.list().await?.is_empty() {
+            return Err(err!(
+                "The app is running, stop it first (see the `ps` command)"
+            ));
+        }
+        let mut paths = Vec::new();
+        for dir in self.cacher.launcher_dirs() {
+            if self.cacher.is_own_dir(dir).await? {
+                paths.push(dir.clone());
+            } else if fs::try_exists(dir).await? {
+                let warn = format!(
+                    "{} is not created by the launcher, remove files of the launcher there yourself",
+                    dir.display()
+                );
+                println!("{}", warn.yellow());
+            }
+        }
+        let confirmed = confirm_removal(paths, opts.dry_run, opts.yes).await?;
+        if let Some(paths) = &confirmed {
+            for path in paths {
+                disk::remove(path).await?;
+            }
+            if !paths.is_empty() {
+                println!("The folders of the launcher are removed");
+            }
+        } else if !opts.dry_run {
+            return Ok(());
+        }
+
+        let exe = std::env::current_exe()?;
+        let cargo_bin = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+            .map(|cargo_home| cargo_home.join("bin"));
+        let by_cargo = cargo_bin.map(|bin| exe.starts_with(bin)).unwrap_or(false);
+        let command = if by_cargo {
+            format!("cargo uninstall {}", built_info::PKG_NAME)
+        } else if cfg!(windows) {
+            format!("del \"{}\"", exe.display())
+        } else {
+            format!("rm {}", exe.display())
+        };
+        println!("To remove the launcher itself run:");
+        println!("  {}", command.green());
+        let run = !opts.dry_run
+            && confirmed.is_some()
+            && (opts.yes
+                || std::io::stdin().is_terminal()
+                    && Confirm::new()
+                        .with_prompt("Do you want to run it now?")
+                        .default(false)
+                        .interact()?);
+        if run {
+            if by_cargo {
+                let status = Command::new("cargo")
+                    .args(["uninstall", built_info::PKG_NAME])
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(err!("Can't uninstall the launcher: {status}"));
+                }
+            } else {
+                fs::remove_file(&exe).await?;
+            }
+            println!("The launcher is uninstalled");
+        }
+        Ok(())
+    }
+
+    pub async fn command_profile(&mut self, opts: ProfileCommand) -> Result<(), Error> {
+        match opts.action {
+            ProfileAction::List => {
+                for name in self.cacher.profiles().await? {
+                    if name == self.cacher.profile() {
+                        println!("{} {}", name.green(), "(active)".dimmed());
+                    } else {
+                        println!("{name}");
+                    }
+                }
+            }
+            ProfileAction::Create { name } => {
+                let mut cacher = self.profile_cacher(&name).await?;
+                if cacher.profile_exists().await? {
+                    return Err(err!("The profile {name} exists already"));
+                }
+                cacher.initialize().await?;
+                println!("The profile {name} is created, use it with `--profile {name}`");
+            }
+            ProfileAction::Delete { name, yes } => {
+                let cacher = self.profile_cacher(&name).await?;
+                if cacher.profile() == DEFAULT_PROFILE {
+                    return Err(err!("The default profile can't be deleted"));
+                }
+                if cacher.profile() == self.cacher.profile() {
+                    return Err(err!("The profile {name} is active, switch to another one"));
+                }
+                if !cacher.profile_exists().await? {
+                    return Err(err!("The profile {name} doesn't exist"));
+                }
+                let registry = Registry::new(cacher.instances_dir().clone());
+                if !registry.list().await?.is_empty() {
+                    return Err(err!(
+                        "The app is running in the profile {name}, stop it first"
+                    ));
+                }
+                let paths = cacher.profile_dirs().into_iter().cloned().collect();
+                if let Some(paths) = confirm_removal(paths, false, yes).await? {
+                    for path in &paths {
+                        disk::remove(path).await?;
+                    }
+                    println!("The profile {name} is deleted");
+                }
+            }
+            ProfileAction::Copy { from, to } => {
+                let mut source = self.profile_cacher(&from).await?;
+                if !source.profile_exists().await? {
+                    return Err(err!("The profile {from} doesn't exist"));
+                }
+                let mut target = self.profile_cacher(&to).await?;
+                if target.profile_exists().await? {
+                    return Err(err!("The profile {to} exists already"));
+                }
+                source.initialize().await?;
+                target.initialize().await?;
+                fs::copy(source.config_path(), target.config_path()).await?;
+                fs::copy(source.state_path(), target.state_path()).await?;
+                // The version of the profile works without a download
+                if let Some(version) = &source.ri_learn.version {
+                    let dir = source.version_dir(&app_info::LEARN, version);
+                    if fs::try_exists(&dir).await? {
+                        let target_dir = target.version_dir(&app_info::LEARN, version);
+                        disk::copy_dir(&dir, &target_dir).await?;
+                    }
+                }
+                println!("The profile {to} is created from {from}");
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn command_auth(&mut self, opts: AuthCommand) -> Result<(), Error> {
+        let path = self.cacher.credentials_path().clone();
+        match opts.action {
+            AuthAction::Login { token } => {
+                let token = match token {
+                    Some(token) => token,
+                    None if std::io::stdin().is_terminal() => Password::new()
+                        .with_prompt("A token of GitHub")
+                        .interact()?,
+                    None => {
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line)?;
+                        line
+                    }
+                };
+                let token = token.trim();
+                if token.is_empty() {
+                    return Err(err!("The token is empty"));
+                }
+                self.credentials.github_token = Some(token.into());
+                self.credentials.save(&path).await?;
+                self.github_api.set_token(Some(token.into()));
+                println!("The token is stored in {}", path.display());
+            }
+            AuthAction::Logout => {
+                if self.credentials.github_token.take().is_some() {
+                    self.credentials.save(&path).await?;
+                    println!("The token is removed");
+                } else {
+                    println!("No token is stored");
+                }
+            }
+            AuthAction::Status => {
+                match credentials::github_token(self.cacher.config(), &self.credentials) {
+                    Some((_token, source)) => println!("The token is taken from {source}"),
+                    None => println!(
+                        "No token, set {TOKEN_VAR} or run `knowledge auth login` to add one"
+                    ),
+                }
+                let limit = self.github_api.rate_limit().await?;
+                let reset = limit.reset.with_timezone(&Local).format("%H:%M");
+                println!(
+                    "Requests left: {} of {}, the limit resets at {reset}",
+                    limit.remaining.to_string().green(),
+                    limit.limit
+                );
             }
         }
         Ok(())
     }
 
-    pub async fn command_clean(self) -> Result<(), Error> {
-        if Confirm::new()
-            .with_prompt("Do you want to clean the cache?")
+    pub async fn command_import(&mut self, opts: ImportCommand) -> Result<(), Error> {
+        // The bundle is a folder with assets downloaded from a release
+        let paths = if fs::metadata(&opts.path).await?.is_dir() {
+            let mut paths = Vec::new();
+            let mut entries = fs::read_dir(&opts.path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                paths.push(entry.path());
+            }
+            paths
+        } else {
+            vec![opts.path.clone()]
+        };
+        let os = self.cacher.system.clone();
+        let mut apps = Vec::new();
+        let mut templates = Vec::new();
+        for path in paths {
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if let Some(version) = github::parse_asset_name(&app_info::LEARN, &file_name, &os) {
+                apps.push((version, path));
+            } else if let Some(course) = github::parse_template_name(&app_info::LEARN, &file_name) {
+                templates.push((course.to_string(), path));
+            }
+        }
+        if apps.is_empty() && templates.is_empty() {
+            let expected = github::asset_name(&app_info::LEARN, &"<version>", &os);
+            return Err(err!(
+                "Expected an archive of the app named {expected} or a folder with it"
+            ));
+        }
+        apps.sort();
+        for (version, _) in &apps {
+            if !self.cacher.policy().allows(version) {
+                return Err(err!(
+                    "The version {version} is not allowed by the policy {}",
+                    self.cacher.policy_path().display()
+                ));
+            }
+        }
+        let _lock = self.cacher.lock(LockMode::Exclusive).await?;
+        self.cacher.reload_state().await?;
+        let mut imported = None;
+        for (version, path) in apps {
+            let tar_gz = std::fs::File::open(&path)?;
+            self.unpack_ri_learn(&version, tar_gz).await?;
+            imported = Some(version);
+        }
+        // Templates belong to the release imported with them or to the installed one
+        let release = imported
+            .clone()
+            .or_else(|| self.cacher.ri_learn.version.clone());
+        for (course, path) in templates {
+            let version = release
+                .as_ref()
+                .ok_or_else(|| err!("Import the template together with the archive of the app"))?;
+            println!("Importing the template of '{course}'...");
+            let archive = fs::File::open(&path).await?;
+            self.save_template(&course, version, archive).await?;
+        }
+        if let Some(version) = imported {
+            self.switch_ri_learn(version).await?;
+        }
+        Ok(())
+    }
+
+    /// Folders of another profile with the same options of the launcher
+    async fn profile_cacher(&self, name: &str) -> Result<Cacher, Error> {
+        let opts = &self.opts;
+        Cacher::create(opts.home.as_deref(), opts.system_install, Some(name)).await
+    }
+}
+
+/// Stored credentials, they're not used if they can't be read
+async fn load_credentials(cacher: &Cacher) -> Credentials {
+    let path = cacher.credentials_path();
+    Credentials::load(path).await.unwrap_or_else(|err| {
+        let warn = format!("{err}\nThe stored credentials are not used.");
+        println!("{}", warn.yellow());
+        Credentials::default()
+    })
+}
+
+/// Prints paths with their sizes and asks to remove them.
+///
+/// Returns paths that exist if the removal is confirmed, never for a dry run.
+async fn confirm_removal(
+    paths: Vec<PathBuf>,
+    dry_run: bool,
+    yes: bool,
+) -> Result<Option<Vec<PathBuf>>, Error> {
+    let mut existing = Vec::new();
+    let mut total = 0;
+    for path in paths {
+        if fs::try_exists(&path).await? {
+            let size = disk::size_of(&path).await?;
+            total += size;
+            existing.push((path, size));
+        }
+    }
+    if existing.is_empty() {
+        println!("Nothing to remove");
+        // Nothing is changed by a dry run, even with nothing to remove
+        return Ok((!dry_run).then(Vec::new));
+    }
+    println!("The following will be removed:");
+    for (path, size) in &existing {
+        println!("  {:>10}  {}", disk::format_size(*size), path.display());
+    }
+    println!("Space to free: {}", disk::format_size(total).green());
+    if dry_run {
+        return Ok(None);
+    }
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            return Err(err!("Use --yes to confirm the removal without a terminal"));
+        }
+        if !Confirm::new()
+            .with_prompt("Do you want to remove them?")
+            .default(false)
             .interact()?
         {
-            println!("Cleaning the cache...");
-            self.cacher.remove_cache().await?;
+            return Ok(None);
         }
-        Ok(())
     }
+    Ok(Some(existing.into_iter().map(|(path, _)| path).collect()))
 }
@@ -1,38 +1,118 @@
-use crate::{app_info, built_info, VERSION};
-use anyhow::Error;
+use crate::app_info::{self, AppInfo};
+use crate::environment::EnvFilter;
+use crate::http::NetworkConfig;
+use crate::lock::{FileLock, LockMode};
+use crate::logs::LogsConfig;
+use crate::policy::{self, Policy};
+use crate::preferences::Preferences;
+use crate::retention::RetentionConfig;
+use crate::snapshot::SnapshotConfig;
+use crate::supervisor::SupervisorConfig;
+use crate::workspace::History;
+use crate::{built_info, disk, VERSION};
+use anyhow::{anyhow as err, Error};
 use chrono::{DateTime, Duration, Utc};
+use colored::Colorize;
 use derive_more::{Deref, DerefMut};
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::fs::File;
 
+const HOME_VAR: &str = "KNOWLEDGE_HOME";
+const CACHE_DIR_VAR: &str = "KNOWLEDGE_CACHE_DIR";
+const CONFIG_DIR_VAR: &str = "KNOWLEDGE_CONFIG_DIR";
+const DATA_DIR_VAR: &str = "KNOWLEDGE_DATA_DIR";
+/// Chooses the profile like the `--profile` argument
+const PROFILE_VAR: &str = "KNOWLEDGE_PROFILE";
+/// The profile that keeps its files right in the folders of the launcher
+pub const DEFAULT_PROFILE: &str = "default";
+/// The folder of named profiles inside the cache, config and state folders
+const PROFILES_DIR: &str = "profiles";
+/// Overrides the folder of the installation shared by all users
+const SYSTEM_DIR_VAR: &str = "KNOWLEDGE_SYSTEM_DIR";
+/// The file next to the executable that turns on the portable mode
+const PORTABLE_MARKER: &str = "knowledge.portable";
+/// The home folder next to the executable in the portable mode
+const PORTABLE_HOME: &str = "knowledge-home";
+
+/// The file in folders created by the launcher, only they are removed as a whole
+const OWNER_MARKER: &str = ".knowledge-launcher";
+const LOCK_FILE: &str = "launcher.lock";
+/// The version of the layout of the state file
+const SCHEMA_VERSION: i64 = 1;
+/// Steps that upgrade the state, the first one upgrades the version 1 to 2
+const MIGRATIONS: &[fn(&mut toml::Table)] = &[];
+const CONFIG_FILE: &str = "config.toml";
+const STATE_FILE: &str = "state.toml";
+const CREDENTIALS_FILE: &str = "credentials.toml";
+/// The file that kept both the config and the state in the cache folder
+const LEGACY_STATE_FILE: &str = "launcher.toml";
+
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct GlobalConfig {
-    pub system: String,
+    /// Seconds to wait for the app to stop before killing it
+    pub grace_period: u64,
+    pub supervisor: SupervisorConfig,
+    pub logs: LogsConfig,
+    pub env_filter: EnvFilter,
+    pub snapshots: SnapshotConfig,
+    pub retention: RetentionConfig,
+    pub network: NetworkConfig,
 }
 
 impl Default for GlobalConfig {
     fn default() -> Self {
         Self {
-            system: built_info::CFG_OS.into(),
+            grace_period: 10,
+            supervisor: SupervisorConfig::default(),
+            logs: LogsConfig::default(),
+            env_filter: EnvFilter::default(),
+            snapshots: SnapshotConfig::default(),
+            retention: RetentionConfig::default(),
+            network: NetworkConfig::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct LauncherConfig {
+impl GlobalConfig {
+    pub fn grace_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.grace_period)
+    }
+}
+
+/// Preferences of the user, never changed by the launcher
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UserConfig {
     pub global: GlobalConfig,
+    pub preferences: Preferences,
+    /// Variables set for the app
+    pub env: BTreeMap<String, String>,
+}
+
+/// What the launcher remembers between runs
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LauncherState {
+    pub schema_version: i64,
+    /// The system of installed assets
+    pub system: String,
     pub launcher: AppState,
     pub ri_learn: AppState,
     pub ri_stack: AppState,
+    #[serde(default)]
+    pub history: History,
 }
 
-impl Default for LauncherConfig {
+impl Default for LauncherState {
     fn default() -> Self {
         Self {
-            global: GlobalConfig::default(),
+            schema_version: SCHEMA_VERSION,
+            system: built_info::CFG_OS.into(),
             launcher: AppState {
                 version: Some(VERSION.clone()),
                 last_check: None,
@@ -45,10 +125,31 @@ impl Default for LauncherConfig {
                 version: None,
                 last_check: None,
             },
+            history: History::default(),
         }
     }
 }
 
+/// The layout of `launcher.toml` before the config and the state were split
+#[derive(Debug, Deserialize)]
+struct LegacyState {
+    global: LegacyGlobal,
+    launcher: AppState,
+    ri_learn: AppState,
+    ri_stack: AppState,
+    #[serde(default)]
+    history: History,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LegacyGlobal {
+    system: String,
+    #[serde(flatten)]
+    config: GlobalConfig,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppState {
     pub version: Option<Version>,
@@ -56,16 +157,22 @@ pub struct AppState {
 }
 
 impl AppState {
-    pub fn is_update_required(&self) -> bool {
-        self.is_not_exist() || self.is_allowed_to_check()
+    pub fn is_update_required(&self, interval: std::time::Duration) -> bool {
+        self.is_not_exist() || self.is_allowed_to_check(interval)
     }
 
     pub fn is_not_exist(&self) -> bool {
         self.version.is_none()
     }
 
-    pub fn is_allowed_to_check(&self) -> bool {
-        let deadline = Utc::now() - Duration::days(1);
+    pub fn is_allowed_to_check(&self, interval: std::time::Duration) -> bool {
+        // Too long intervals mean it's never checked again
+        let deadline = Duration::from_std(interval)
+            .ok()
+            .and_then(|interval| Utc::now().checked_sub_signed(interval));
+        let Some(deadline) = deadline else {
+            return self.last_check.is_none();
+        };
         match &self.last_check {
             None => true,
             Some(last) if last <= &deadline => true,
@@ -97,51 +204,318 @@ impl AppState {
     }
 }
 
+/// Folders of the launcher.
+///
+/// Binaries and downloads are kept in the cache that is safe to remove,
+/// preferences in the config folder, and durable state in the data folder.
 #[derive(Debug, Deref, DerefMut)]
 pub struct Cacher {
+    /// The named profile, `None` for the default one
+    profile: Option<String>,
+    /// Folders of the launcher shared by all profiles
+    roots: Vec<PathBuf>,
     cache_dir: PathBuf,
+    config_dir: PathBuf,
+    state_dir: PathBuf,
     bin_dir: PathBuf,
+    /// Binaries installed by an administrator for all users, read-only
+    shared_bin_dir: Option<PathBuf>,
+    /// The launcher installs binaries for all users
+    system_install: bool,
+    lock_path: PathBuf,
+    templates_dir: PathBuf,
+    metadata_dir: PathBuf,
+    config_path: PathBuf,
+    credentials_path: PathBuf,
     state_path: PathBuf,
+    instances_dir: PathBuf,
+    logs_dir: PathBuf,
+    snapshots_dir: PathBuf,
+    policy_path: PathBuf,
+    policy: Policy,
+    config: UserConfig,
     #[deref]
     #[deref_mut]
-    state: LauncherConfig,
+    state: LauncherState,
 }
 
 impl Cacher {
-    pub async fn create() -> Result<Self, Error> {
+    /// Finds folders of the launcher.
+    ///
+    /// The `home` folder keeps all the folders inside, it's taken from the
+    /// `--home` argument, the `KNOWLEDGE_HOME` variable or the portable mode.
+    /// Variables of individual folders override it.
+    ///
+    /// With `system_install` binaries are installed to the shared folder
+    /// instead of the cache of the user.
+    ///
+    /// A named `profile` (or `KNOWLEDGE_PROFILE`) keeps its config, state
+    /// and binaries apart, snapshots are shared by all profiles.
+    pub async fn create(
+        home: Option<&Path>,
+        system_install: bool,
+        profile: Option<&str>,
+    ) -> Result<Self, Error> {
+        let home = match home {
+            Some(home) => Some(home.to_path_buf()),
+            None => std::env::var_os(HOME_VAR)
+                .filter(|home| !home.is_empty())
+                .map(PathBuf::from)
+                .or_else(portable_home),
+        };
+        let home = home.map(std::path::absolute).transpose()?;
+        let home = home.as_deref();
+
         // Create paths
-        let mut cache_dir =
-            dirs::cache_dir().ok_or_else(|| Error::msg("Cache directory is not available."))?;
-        cache_dir.push("rustinsight");
+        let cache_dir = locate(CACHE_DIR_VAR, home, "cache", dirs::cache_dir())?;
+        let config_dir = locate(CONFIG_DIR_VAR, home, "config", dirs::config_dir())?;
+        let data_dir = locate(DATA_DIR_VAR, home, "data", dirs::data_dir())?;
+        // Only Linux has a separate folder for the state
+        let platform_state_dir = dirs::state_dir().or_else(dirs::data_local_dir);
+        let state_dir = locate(DATA_DIR_VAR, home, "state", platform_state_dir)?;
 
+        let profile = match profile {
+            Some(profile) => Some(profile.to_string()),
+            None => std::env::var(PROFILE_VAR)
+                .ok()
+                .filter(|name| !name.is_empty()),
+        };
+        let profile = profile.filter(|name| name != DEFAULT_PROFILE);
+        if let Some(name) = &profile {
+            check_profile_name(name)?;
+        }
+        let mut roots = vec![
+            cache_dir.clone(),
+            config_dir.clone(),
+            data_dir.clone(),
+            state_dir.clone(),
+        ];
+        roots.sort();
+        roots.dedup();
+        let in_profile = |root: PathBuf| match &profile {
+            Some(name) => root.join(PROFILES_DIR).join(name),
+            None => root,
+        };
+        let cache_dir = in_profile(cache_dir);
+        let config_dir = in_profile(config_dir);
+        let state_dir = in_profile(state_dir);
+
+        let system_dir = system_dir();
         let mut bin_dir = cache_dir.clone();
         bin_dir.push("bin");
+        let mut shared_bin_dir = system_dir.join("bin");
+        let mut lock_path = cache_dir.join(LOCK_FILE);
+        if system_install {
+            std::mem::swap(&mut bin_dir, &mut shared_bin_dir);
+            lock_path = system_dir.join(LOCK_FILE);
+        }
+        // The shared folder is only read by launchers of users
+        let shared_bin_dir = (!system_install).then_some(shared_bin_dir);
+
+        let mut templates_dir = cache_dir.clone();
+        templates_dir.push("templates");
 
-        let mut state_path = cache_dir.clone();
-        state_path.push("launcher.toml");
+        let mut metadata_dir = cache_dir.clone();
+        metadata_dir.push("metadata");
+
+        let mut config_path = config_dir.clone();
+        config_path.push(CONFIG_FILE);
+
+        let mut credentials_path = config_dir.clone();
+        credentials_path.push(CREDENTIALS_FILE);
+
+        let mut state_path = state_dir.clone();
+        state_path.push(STATE_FILE);
+
+        let mut instances_dir = state_dir.clone();
+        instances_dir.push("instances");
+
+        let mut logs_dir = state_dir.clone();
+        logs_dir.push("logs");
+
+        let mut snapshots_dir = data_dir.clone();
+        snapshots_dir.push("snapshots");
 
-        let state = LauncherConfig::default();
         Ok(Self {
+            profile,
+            roots,
             cache_dir,
+            config_dir,
+            state_dir,
             bin_dir,
+            shared_bin_dir,
+            system_install,
+            lock_path,
+            templates_dir,
+            metadata_dir,
+            config_path,
+            credentials_path,
             state_path,
-            state,
+            instances_dir,
+            logs_dir,
+            snapshots_dir,
+            policy_path: policy::path(),
+            policy: Policy::default(),
+            config: UserConfig::default(),
+            state: LauncherState::default(),
         })
     }
 
     pub async fn initialize(&mut self) -> Result<(), Error> {
         self.create_dirs().await?;
-        if let Err(_err) = self.try_read_state().await {
-            // Can't read a config file, it doesn't exist.
-        }
+        let _lock = self.lock(LockMode::Exclusive).await?;
+        self.migrate_legacy_state().await?;
+        self.policy = Policy::load(&self.policy_path).await?;
+        let mut config = if !fs::try_exists(&self.config_path).await? {
+            // A template of the config for the user to edit
+            self.write_config().await?;
+            toml::Table::new()
+        } else {
+            self.try_read_config().await.unwrap_or_else(|err| {
+                let path = self.config_path.display();
+                let warn = format!("Can't read {path}: {err}\nThe default settings are used.");
+                println!("{}", warn.yellow());
+                toml::Table::new()
+            })
+        };
+        self.policy.apply(&mut config);
+        self.config = config.try_into().map_err(|e| {
+            err!(
+                "Invalid settings in the policy {}: {e}",
+                self.policy_path.display()
+            )
+        })?;
+        self.read_state().await?;
+        self.migrate_legacy_binary().await?;
         self.repair_config().await?; // In case if something removed
         self.write_state().await?;
         Ok(())
     }
 
     async fn create_dirs(&mut self) -> Result<(), Error> {
+        // Before anything is created there
+        self.mark_own_dirs().await?;
         // Create dirs
-        fs::create_dir_all(&self.bin_dir).await?;
+        if let Err(e) = fs::create_dir_all(&self.bin_dir).await {
+            if self.system_install && e.kind() == ErrorKind::PermissionDenied {
+                return Err(err!(
+                    "Can't write to {}, run the installation for all users as an administrator",
+                    self.bin_dir.display()
+                ));
+            }
+            return Err(e.into());
+        }
+        fs::create_dir_all(&self.templates_dir).await?;
+        fs::create_dir_all(&self.metadata_dir).await?;
+        for path in [&self.config_path, &self.state_path] {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).await?;
+            }
+        }
+        fs::create_dir_all(&self.snapshots_dir).await?;
+        Ok(())
+    }
+
+    /// Marks folders that belong to the launcher: the default ones and
+    /// the ones chosen by the user that didn't exist or were empty.
+    async fn mark_own_dirs(&self) -> Result<(), Error> {
+        let platform_roots = platform_roots();
+        for root in &self.roots {
+            let marker = root.join(OWNER_MARKER);
+            if fs::try_exists(&marker).await? {
+                continue;
+            }
+            let own = platform_roots.contains(root)
+                || match fs::read_dir(root).await {
+                    Ok(mut entries) => entries.next_entry().await?.is_none(),
+                    Err(e) if e.kind() == ErrorKind::NotFound => true,
+                    Err(e) => return Err(e.into()),
+                };
+            if own {
+                fs::create_dir_all(root).await?;
+                let note = "The folder is created by the knowledge launcher\n\
+                            and removed by `knowledge uninstall`.\n";
+                fs::write(&marker, note).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits `launcher.toml` of the cache folder into the config and the state.
+    ///
+    /// Also moves instances and logs out of the cache.
+    async fn migrate_legacy_state(&mut self) -> Result<(), Error> {
+        let legacy_path = self.cache_dir.join(LEGACY_STATE_FILE);
+        let contents = match fs::read_to_string(&legacy_path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return self.create_state_dirs().await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !fs::try_exists(&self.state_path).await? {
+            match toml::from_str::<LegacyState>(&contents) {
+                Ok(legacy) => {
+                    println!("Moving the settings of the launcher out of the cache...");
+                    self.config = UserConfig {
+                        global: legacy.global.config,
+                        preferences: Preferences::default(),
+                        env: legacy.env,
+                    };
+                    self.state = LauncherState {
+                        schema_version: SCHEMA_VERSION,
+                        system: legacy.global.system,
+                        launcher: legacy.launcher,
+                        ri_learn: legacy.ri_learn,
+                        ri_stack: legacy.ri_stack,
+                        history: legacy.history,
+                    };
+                    if !fs::try_exists(&self.config_path).await? {
+                        self.write_config().await?;
+                    }
+                    self.write_state().await?;
+                }
+                Err(err) => {
+                    warn_broken(&legacy_path, &backup(&legacy_path).await?, &err.into());
+                    return self.create_state_dirs().await;
+                }
+            }
+            move_dir(&self.cache_dir.join("instances"), &self.instances_dir).await?;
+            move_dir(&self.cache_dir.join("logs"), &self.logs_dir).await?;
+        }
+        fs::remove_file(&legacy_path).await?;
+        self.create_state_dirs().await
+    }
+
+    /// Moves the binary of the layout without versions to the folder of its version.
+    ///
+    /// The binary takes the name of the folder of versions, it's removed
+    /// if its version is unknown.
+    async fn migrate_legacy_binary(&self) -> Result<(), Error> {
+        let legacy_path = self.bin_dir.join(app_info::LEARN.name);
+        match fs::metadata(&legacy_path).await {
+            Ok(metadata) if !metadata.is_dir() => {}
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        // Frees the name for the folder, an interrupted move is collected as garbage
+        let moved_path = legacy_path.with_extension("legacy");
+        fs::rename(&legacy_path, &moved_path).await?;
+        match &self.ri_learn.version {
+            Some(version) => {
+                let dir = self.version_dir(&app_info::LEARN, version);
+                fs::create_dir_all(&dir).await?;
+                fs::rename(&moved_path, dir.join(app_info::LEARN.name)).await?;
+            }
+            None => fs::remove_file(&moved_path).await?,
+        }
+        Ok(())
+    }
+
+    async fn create_state_dirs(&self) -> Result<(), Error> {
+        fs::create_dir_all(&self.instances_dir).await?;
+        fs::create_dir_all(&self.logs_dir).await?;
         Ok(())
     }
 
@@ -150,10 +524,9 @@ impl Cacher {
         // Update launcher's version to the current
         self.launcher.version = Some(VERSION.clone());
         // Checking binaries
-        let mut entries = fs::read_dir(&self.bin_dir).await?;
-        let app_prefix = &app_info::LEARN.name;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_name().to_string_lossy().starts_with(app_prefix) {
+        if let Some(version) = self.ri_learn.version.clone() {
+            let available = self.available_versions(&app_info::LEARN).await?;
+            if available.contains(&version) {
                 return Ok(());
             }
         }
@@ -162,13 +535,218 @@ impl Cacher {
         Ok(())
     }
 
+    /// Versions of the app unpacked to the `bin` folder, the oldest first
+    pub async fn installed_versions(&self, app_info: &AppInfo) -> Result<Vec<Version>, Error> {
+        versions_in(&self.bin_dir, app_info).await
+    }
+
+    /// Versions of the app installed for all users, the oldest first
+    pub async fn shared_versions(&self, app_info: &AppInfo) -> Result<Vec<Version>, Error> {
+        match &self.shared_bin_dir {
+            Some(dir) => versions_in(dir, app_info).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Versions that can be launched, installed by the user or for all users
+    pub async fn available_versions(&self, app_info: &AppInfo) -> Result<Vec<Version>, Error> {
+        let mut versions = self.installed_versions(app_info).await?;
+        versions.extend(self.shared_versions(app_info).await?);
+        versions.sort();
+        versions.dedup();
+        Ok(versions)
+    }
+
+    /// The folder with binaries of the version of the app
+    pub fn version_dir(&self, app_info: &AppInfo, version: &Version) -> PathBuf {
+        let mut path = self.bin_dir.join(app_info.name);
+        path.push(version.to_string());
+        path
+    }
+
+    /// The executable of the version of the app, the own installation is preferred
+    pub fn app_path(&self, app_info: &AppInfo, version: &Version) -> PathBuf {
+        let mut dir = self.version_dir(app_info, version);
+        if let Some(shared_dir) = &self.shared_bin_dir {
+            if !dir.exists() {
+                dir = shared_dir.join(app_info.name).join(version.to_string());
+            }
+        }
+        dir.join(app_info.name)
+    }
+
+    /// Locks the cache and the state against other launchers
+    pub async fn lock(&self, mode: LockMode) -> Result<FileLock, Error> {
+        FileLock::acquire(&self.lock_path, mode).await
+    }
+
+    /// All folders of the launcher with all profiles
+    pub fn launcher_dirs(&self) -> Vec<&PathBuf> {
+        self.roots.iter().collect()
+    }
+
+    /// Checks the folder was created by the launcher and can be removed
+    pub async fn is_own_dir(&self, dir: &Path) -> Result<bool, Error> {
+        Ok(fs::try_exists(dir.join(OWNER_MARKER)).await?)
+    }
+
+    /// The name of the active profile
+    pub fn profile(&self) -> &str {
+        self.profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+
+    /// Folders of the active profile, empty for the default one
+    pub fn profile_dirs(&self) -> Vec<&PathBuf> {
+        if self.profile.is_none() {
+            return Vec::new();
+        }
+        let mut dirs = vec![&self.cache_dir, &self.config_dir, &self.state_dir];
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Checks the profile was created before
+    pub async fn profile_exists(&self) -> Result<bool, Error> {
+        Ok(self.profile.is_none() || fs::try_exists(&self.config_path).await?)
+    }
+
+    /// Names of all profiles, the default one first
+    pub async fn profiles(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        for root in &self.roots {
+            let mut entries = match fs::read_dir(root.join(PROFILES_DIR)).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names.insert(0, DEFAULT_PROFILE.into());
+        Ok(names)
+    }
+
+    /// Binaries of versions of the app that are not used anymore
+    pub async fn old_binaries(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut paths = self.legacy_binaries().await?;
+        for version in self.installed_versions(&app_info::LEARN).await? {
+            if self.ri_learn.version.as_ref() != Some(&version) {
+                paths.push(self.version_dir(&app_info::LEARN, &version));
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Files left in the `bin` folder by the layout without versions
+    pub async fn legacy_binaries(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut paths = Vec::new();
+        let mut entries = fs::read_dir(&self.bin_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let is_versions_dir = entry.file_name().to_string_lossy() == app_info::LEARN.name
+                && entry.file_type().await?.is_dir();
+            if !is_versions_dir {
+                paths.push(entry.path());
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Rules of the organisation
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    pub fn policy_path(&self) -> &PathBuf {
+        &self.policy_path
+    }
+
+    /// Preferences of the user with settings of the policy
+    pub fn config(&self) -> &UserConfig {
+        &self.config
+    }
+
+    pub fn config_path(&self) -> &PathBuf {
+        &self.config_path
+    }
+
+    /// Secrets of the user, readable only by the user
+    pub fn credentials_path(&self) -> &PathBuf {
+        &self.credentials_path
+    }
+
+    pub fn state_path(&self) -> &PathBuf {
+        &self.state_path
+    }
+
+    pub fn cache_dir(&self) -> &PathBuf {
+        &self.cache_dir
+    }
+
     pub fn bin_dir(&self) -> &PathBuf {
         &self.bin_dir
     }
 
+    pub fn instances_dir(&self) -> &PathBuf {
+        &self.instances_dir
+    }
+
+    pub fn logs_dir(&self) -> &PathBuf {
+        &self.logs_dir
+    }
+
+    /// Templates of workspaces, downloaded or imported with a bundle
+    pub fn templates_dir(&self) -> &PathBuf {
+        &self.templates_dir
+    }
+
+    /// The template of the course from the release
+    pub fn template_path(&self, app_info: &AppInfo, course: &str, version: &Version) -> PathBuf {
+        let name = format!("{}-template-{course}-{version}.tar.gz", app_info.name);
+        self.templates_dir.join(name)
+    }
+
+    /// Templates of the course in the cache, the oldest release first
+    pub async fn cached_templates(
+        &self,
+        app_info: &AppInfo,
+        course: &str,
+    ) -> Result<Vec<(Version, PathBuf)>, Error> {
+        let prefix = format!("{}-template-{course}-", app_info.name);
+        let mut templates = Vec::new();
+        let mut entries = fs::read_dir(&self.templates_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let version = file_name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".tar.gz"))
+                .and_then(|version| version.parse::<Version>().ok());
+            if let Some(version) = version {
+                templates.push((version, entry.path()));
+            }
+        }
+        templates.sort();
+        Ok(templates)
+    }
+
+    /// Metadata of releases and the launcher, revalidated and used offline
+    pub fn metadata_dir(&self) -> &PathBuf {
+        &self.metadata_dir
+    }
+
+    /// Snapshots of working folders, kept apart from the cache
+    pub fn snapshots_dir(&self) -> &PathBuf {
+        &self.snapshots_dir
+    }
+
     /// Changes permissions and extension
-    pub async fn fix_binaries(&mut self) -> Result<(), Error> {
-        let mut entries = fs::read_dir(&self.bin_dir).await?;
+    pub async fn fix_binaries(&self, dir: &Path) -> Result<(), Error> {
+        let mut entries = fs::read_dir(dir).await?;
         while let Some(entry) = entries.next_entry().await? {
             let bin_file = File::open(entry.path()).await?;
             // Changes permissions on unix-like systems
@@ -176,7 +754,9 @@ impl Cacher {
             {
                 use std::os::unix::fs::PermissionsExt;
                 let mut perm = bin_file.metadata().await?.permissions();
-                let new_mode = perm.mode() | 0o511; // adds executable permission `(rx)xx`
+                // Adds executable permission `(rx)xx`, shared binaries are readable by all
+                let bits = if self.system_install { 0o555 } else { 0o511 };
+                let new_mode = perm.mode() | bits;
                 perm.set_mode(new_mode);
                 bin_file.set_permissions(perm).await?;
             }
@@ -192,21 +772,421 @@ impl Cacher {
         Ok(())
     }
 
-    async fn try_read_state(&mut self) -> Result<(), Error> {
-        let contents = fs::read_to_string(&self.state_path).await?;
-        let state = toml::from_str(&contents)?;
-        self.state = state;
+    /// Reads the config of the user, the policy is not applied
+    async fn try_read_config(&self) -> Result<toml::Table, Error> {
+        let contents = fs::read_to_string(&self.config_path).await?;
+        toml::from_str::<UserConfig>(&contents)?
+            .preferences
+            .check()?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    async fn write_config(&self) -> Result<(), Error> {
+        let contents = toml::to_string(&self.config)?;
+        disk::write_atomic(&self.config_path, &contents).await
+    }
+
+    /// Takes changes made by another launcher
+    pub async fn reload_state(&mut self) -> Result<(), Error> {
+        self.read_state().await
+    }
+
+    /// Reads and upgrades the state.
+    ///
+    /// An unreadable file is backed up and the launcher starts from scratch.
+    async fn read_state(&mut self) -> Result<(), Error> {
+        let contents = match fs::read_to_string(&self.state_path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut table: toml::Table = match toml::from_str(&contents) {
+            Ok(table) => table,
+            Err(err) => return self.backup_state(err.into()).await,
+        };
+        let version = table
+            .get("schema_version")
+            .and_then(toml::Value::as_integer)
+            .unwrap_or(1)
+            .max(1);
+        if version > SCHEMA_VERSION {
+            return Err(err!(
+                "{} is written by a newer launcher, update the launcher to use it",
+                self.state_path.display()
+            ));
+        }
+        for migration in MIGRATIONS.iter().skip(version as usize - 1) {
+            migration(&mut table);
+        }
+        table.insert("schema_version".into(), SCHEMA_VERSION.into());
+        match table.try_into() {
+            Ok(state) => self.state = state,
+            Err(err) => return self.backup_state(err.into()).await,
+        }
+        Ok(())
+    }
+
+    /// Keeps a copy of the broken state for troubleshooting
+    async fn backup_state(&mut self, err: Error) -> Result<(), Error> {
+        let backup_path = backup(&self.state_path).await?;
+        warn_broken(&self.state_path, &backup_path, &err);
+        self.state = LauncherState::default();
         Ok(())
     }
 
+    /// Changes the state under the lock, changes of other launchers are kept
+    pub async fn update_state(
+        &mut self,
+        change: impl FnOnce(&mut LauncherState),
+    ) -> Result<(), Error> {
+        let _lock = self.lock(LockMode::Exclusive).await?;
+        self.reload_state().await?;
+        change(&mut self.state);
+        self.write_state().await
+    }
+
     pub async fn write_state(&mut self) -> Result<(), Error> {
         let contents = toml::to_string(&self.state)?;
-        fs::write(&self.state_path, contents).await?;
-        Ok(())
+        disk::write_atomic(&self.state_path, &contents).await
     }
+}
 
-    pub async fn remove_cache(self) -> Result<(), Error> {
-        fs::remove_dir_all(self.cache_dir).await?;
-        Ok(())
+/// Renames the file to `<name>.<timestamp>.bak`
+async fn backup(path: &Path) -> Result<PathBuf, Error> {
+    let timestamp = Utc::now().format("%Y%m%d%H%M%S");
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(format!(".{timestamp}.bak"));
+    let backup_path = PathBuf::from(backup_path);
+    fs::rename(path, &backup_path).await?;
+    Ok(backup_path)
+}
+
+fn warn_broken(path: &Path, backup_path: &Path, err: &Error) {
+    let warn = format!(
+        "Can't read {}: {err}\nThe launcher starts from scratch, the file is saved as {}",
+        path.display(),
+        backup_path.display()
+    );
+    println!("{}", warn.yellow());
+}
+
+/// Takes the folder from the variable, the home folder or the platform
+fn locate(
+    var: &str,
+    home: Option<&Path>,
+    name: &str,
+    platform_dir: Option<PathBuf>,
+) -> Result<PathBuf, Error> {
+    if let Some(dir) = std::env::var_os(var).filter(|dir| !dir.is_empty()) {
+        return Ok(std::path::absolute(dir)?);
+    }
+    if let Some(home) = home {
+        return Ok(home.join(name));
+    }
+    let mut dir = platform_dir.ok_or_else(|| {
+        err!("The {name} directory is not available, set {var} or {HOME_VAR} to choose it")
+    })?;
+    dir.push("rustinsight");
+    Ok(dir)
+}
+
+/// Default folders of the launcher on the platform
+fn platform_roots() -> Vec<PathBuf> {
+    [
+        dirs::cache_dir(),
+        dirs::config_dir(),
+        dirs::data_dir(),
+        dirs::state_dir().or_else(dirs::data_local_dir),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|dir| dir.join("rustinsight"))
+    .collect()
+}
+
+/// The home folder beside the executable if the portable mode is on
+fn portable_home() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let exe_dir = exe.parent()?;
+    if exe_dir.join(PORTABLE_MARKER).is_file() {
+        Some(exe_dir.join(PORTABLE_HOME))
+    } else {
+        None
+    }
+}
+
+/// Versions of the app unpacked to the folder of binaries, the oldest first
+async fn versions_in(bin_dir: &Path, app_info: &AppInfo) -> Result<Vec<Version>, Error> {
+    let mut versions = Vec::new();
+    let app_dir = bin_dir.join(app_info.name);
+    let mut entries = match fs::read_dir(&app_dir).await {
+        Ok(entries) => entries,
+        // The binary of the layout without versions may take the name
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+            return Ok(versions)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(version) = entry.file_name().to_string_lossy().parse::<Version>() else {
+            continue;
+        };
+        if has_binary(&entry.path(), app_info.name).await? {
+            versions.push(version);
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+/// Names of profiles are used as names of folders
+fn check_profile_name(name: &str) -> Result<(), Error> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || !valid {
+        return Err(err!(
+            "Invalid profile name '{name}', use letters, digits, '-' and '_'"
+        ));
+    }
+    Ok(())
+}
+
+/// The folder of the installation shared by all users
+fn system_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(SYSTEM_DIR_VAR).filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    if cfg!(windows) {
+        let program_data =
+            std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+        Path::new(&program_data).join("knowledge")
+    } else {
+        PathBuf::from("/opt/knowledge")
+    }
+}
+
+async fn has_binary(dir: &Path, name: &str) -> Result<bool, Error> {
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with(name) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Moves a folder unless the target exists already
+async fn move_dir(from: &Path, to: &Path) -> Result<(), Error> {
+    if !fs::try_exists(from).await? || fs::try_exists(to).await? {
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    if fs::rename(from, to).await.is_err() {
+        // Different file systems, old logs and instances are not worth copying
+        fs::remove_dir_all(from).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// `launcher.toml` and the binary as they were left by the launcher 0.4
+    const BASELINE_STATE: &str = r#"
+[global]
+system = "linux"
+
+[launcher]
+version = "0.4.4"
+
+[ri_learn]
+version = "0.3.0"
+last_check = "2024-04-01T10:00:00Z"
+
+[ri_stack]
+"#;
+
+    async fn cacher(home: &TempDir) -> Cacher {
+        Cacher::create(Some(home.path()), false, None)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn upgrades_the_baseline_layout() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let bin_dir = cacher.bin_dir().clone();
+        fs::create_dir_all(&bin_dir).await.unwrap();
+        fs::write(bin_dir.join(app_info::LEARN.name), "binary")
+            .await
+            .unwrap();
+        let legacy_path = cacher.cache_dir().join(LEGACY_STATE_FILE);
+        fs::write(&legacy_path, BASELINE_STATE).await.unwrap();
+
+        cacher.initialize().await.unwrap();
+
+        let version: Version = "0.3.0".parse().unwrap();
+        assert_eq!(cacher.ri_learn.version, Some(version.clone()));
+        assert_eq!(cacher.system, "linux");
+        let app_path = cacher.app_path(&app_info::LEARN, &version);
+        assert_eq!(fs::read_to_string(app_path).await.unwrap(), "binary");
+        assert!(cacher.legacy_binaries().await.unwrap().is_empty());
+        assert!(!legacy_path.exists());
+        assert!(cacher.state_path().exists());
+        assert!(cacher.config_path().exists());
+    }
+
+    #[tokio::test]
+    async fn removes_the_legacy_binary_of_unknown_version() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let bin_dir = cacher.bin_dir().clone();
+        fs::create_dir_all(&bin_dir).await.unwrap();
+        fs::write(bin_dir.join(app_info::LEARN.name), "binary")
+            .await
+            .unwrap();
+
+        cacher.initialize().await.unwrap();
+
+        assert!(!bin_dir.join(app_info::LEARN.name).exists());
+        assert!(cacher.ri_learn.version.is_none());
+        assert!(cacher
+            .installed_versions(&app_info::LEARN)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    async fn backups_of(path: &Path) -> Vec<PathBuf> {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let mut backups = Vec::new();
+        let mut entries = fs::read_dir(path.parent().unwrap()).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if file_name.starts_with(&name) && file_name.ends_with(".bak") {
+                backups.push(entry.path());
+            }
+        }
+        backups
+    }
+
+    #[tokio::test]
+    async fn splits_the_legacy_state() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let legacy = format!(
+            "{BASELINE_STATE}\n[history]\nfolders = [\"/tmp/practice\"]\n\n[env]\nRUST_LOG = \"debug\"\n"
+        );
+        let legacy = legacy.replace("[global]\n", "[global]\ngrace_period = 3\n");
+        cacher.create_dirs().await.unwrap();
+        let instances_dir = cacher.cache_dir().join("instances");
+        fs::create_dir_all(&instances_dir).await.unwrap();
+        fs::write(instances_dir.join("1.toml"), "").await.unwrap();
+        fs::write(cacher.cache_dir().join(LEGACY_STATE_FILE), legacy)
+            .await
+            .unwrap();
+
+        cacher.migrate_legacy_state().await.unwrap();
+
+        assert_eq!(cacher.config().global.grace_period, 3);
+        assert_eq!(cacher.config().env["RUST_LOG"], "debug");
+        let state = fs::read_to_string(cacher.state_path()).await.unwrap();
+        let state: LauncherState = toml::from_str(&state).unwrap();
+        assert_eq!(state.schema_version, SCHEMA_VERSION);
+        assert_eq!(state.ri_learn.version, Some("0.3.0".parse().unwrap()));
+        assert_eq!(state.history.folders, vec![PathBuf::from("/tmp/practice")]);
+        assert!(cacher.config_path().exists());
+        assert!(cacher.instances_dir().join("1.toml").exists());
+        assert!(!instances_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn backs_up_the_unreadable_legacy_state() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let legacy_path = cacher.cache_dir().join(LEGACY_STATE_FILE);
+        fs::create_dir_all(cacher.cache_dir()).await.unwrap();
+        fs::write(&legacy_path, "[global").await.unwrap();
+
+        cacher.migrate_legacy_state().await.unwrap();
+
+        assert!(!legacy_path.exists());
+        assert_eq!(backups_of(&legacy_path).await.len(), 1);
+        assert!(!cacher.state_path().exists());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_state_over_the_legacy_one() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let legacy_path = cacher.cache_dir().join(LEGACY_STATE_FILE);
+        fs::create_dir_all(cacher.cache_dir()).await.unwrap();
+        fs::write(&legacy_path, BASELINE_STATE).await.unwrap();
+        fs::create_dir_all(cacher.state_path().parent().unwrap())
+            .await
+            .unwrap();
+        fs::write(cacher.state_path(), "system = \"macos\"")
+            .await
+            .unwrap();
+
+        cacher.migrate_legacy_state().await.unwrap();
+
+        assert!(!legacy_path.exists());
+        let state = fs::read_to_string(cacher.state_path()).await.unwrap();
+        assert_eq!(state, "system = \"macos\"");
+    }
+
+    async fn write_state(cacher: &Cacher, contents: &str) {
+        fs::create_dir_all(cacher.state_path().parent().unwrap())
+            .await
+            .unwrap();
+        fs::write(cacher.state_path(), contents).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_the_state_without_the_schema_version() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let state = "system = \"macos\"\n[launcher]\n[ri_learn]\nversion = \"1.0.0\"\n[ri_stack]\n";
+        write_state(&cacher, state).await;
+
+        cacher.read_state().await.unwrap();
+
+        assert_eq!(cacher.schema_version, SCHEMA_VERSION);
+        assert_eq!(cacher.system, "macos");
+        assert_eq!(cacher.ri_learn.version, Some("1.0.0".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn backs_up_the_unreadable_state() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        write_state(&cacher, "schema_version = 1\nsystem = 5\n").await;
+
+        cacher.read_state().await.unwrap();
+
+        assert!(!cacher.state_path().exists());
+        assert_eq!(backups_of(cacher.state_path()).await.len(), 1);
+        assert_eq!(cacher.system, LauncherState::default().system);
+    }
+
+    #[tokio::test]
+    async fn refuses_the_state_of_a_newer_launcher() {
+        let home = TempDir::new().unwrap();
+        let mut cacher = cacher(&home).await;
+        let state = format!("schema_version = {}\n", SCHEMA_VERSION + 1);
+        write_state(&cacher, &state).await;
+
+        let err = cacher.read_state().await.unwrap_err();
+
+        assert!(err.to_string().contains("newer launcher"));
+        let contents = fs::read_to_string(cacher.state_path()).await.unwrap();
+        assert_eq!(contents, state);
     }
 }
@@ -1,33 +1,43 @@
-use crate::RI_USER_AGENT;
+use crate::http::{HttpClient, ResponseCache};
 use anyhow::Error;
-use reqwest::{header::USER_AGENT, Client};
 use semver::Version;
 use serde::Deserialize;
 
 const BASE: &str = "https://crates.io/api/v1/crates/knowledge";
+const CACHE_KEY: &str = "crate-knowledge";
 
 pub struct CratesApi {
-    client: Client,
+    client: HttpClient,
+    cache: Option<ResponseCache>,
+}
+
+impl Default for CratesApi {
+    fn default() -> Self {
+        Self::new(HttpClient::default())
+    }
 }
 
 impl CratesApi {
-    pub fn new() -> Self {
+    pub fn new(client: HttpClient) -> Self {
         Self {
-            client: Client::new(),
+            client,
+            cache: None,
         }
     }
 
+    /// Revalidates the saved info instead of downloading it every time
+    pub fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
     pub async fn fetch_info(&mut self) -> Result<CratesInfo, Error> {
-        let url = format!("{BASE}");
-        let info = self
+        let (body, _headers) = self
             .client
-            .get(&url)
-            .header(USER_AGENT, RI_USER_AGENT)
-            .send()
-            .await?
-            .json()
+            .get_cached(self.cache.as_ref(), CACHE_KEY, BASE, |url| {
+                self.client.get(url)
+            })
             .await?;
-        Ok(info)
+        Ok(serde_json::from_str(&body)?)
     }
 
     pub async fn latest_version(&mut self) -> Result<Version, Error> {
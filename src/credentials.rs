@@ -0,0 +1,82 @@
+use crate::cacher::UserConfig;
+use crate::disk;
+use anyhow::{anyhow as err, Error};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::Path;
+use tokio::fs;
+
+/// The variable with a token of GitHub, the same as the `gh` tool uses
+pub const TOKEN_VAR: &str = "GITHUB_TOKEN";
+
+/// Secrets of the user, kept apart from the config that is safe to share
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Credentials {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_token: Option<String>,
+}
+
+impl Credentials {
+    /// Reads the file, it's refused if other users can read it
+    pub async fn load(path: &Path) -> Result<Self, Error> {
+        let contents = match fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path).await?.permissions().mode();
+            if mode & 0o077 != 0 {
+                return Err(err!(
+                    "{} is accessible by other users, run `chmod 600` for it",
+                    path.display()
+                ));
+            }
+        }
+        toml::from_str(&contents).map_err(|e| err!("Can't read {}: {e}", path.display()))
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), Error> {
+        disk::write_private(path, &toml::to_string(self)?).await
+    }
+}
+
+/// Where the token of GitHub is taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenSource {
+    Variable,
+    Config,
+    Credentials,
+}
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Variable => write!(f, "the {TOKEN_VAR} variable"),
+            Self::Config => write!(f, "the config"),
+            Self::Credentials => write!(f, "the stored credentials"),
+        }
+    }
+}
+
+/// The token of GitHub, the variable overrides the config and the config
+/// overrides stored credentials
+pub fn github_token(
+    config: &UserConfig,
+    credentials: &Credentials,
+) -> Option<(String, TokenSource)> {
+    let from_var = std::env::var(TOKEN_VAR)
+        .ok()
+        .filter(|token| !token.is_empty());
+    let sources = [
+        (from_var, TokenSource::Variable),
+        (config.preferences.github_token.clone(), TokenSource::Config),
+        (credentials.github_token.clone(), TokenSource::Credentials),
+    ];
+    sources
+        .into_iter()
+        .find_map(|(token, source)| token.map(|token| (token, source)))
+}
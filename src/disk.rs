@@ -0,0 +1,111 @@
+use anyhow::Error;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Bytes taken by the file or the folder with all its content
+pub async fn size_of(path: &Path) -> Result<u64, Error> {
+    let mut size = 0;
+    let mut paths = vec![path.to_path_buf()];
+    while let Some(path) = paths.pop() {
+        let metadata = match fs::symlink_metadata(&path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if metadata.is_dir() {
+            let mut entries = fs::read_dir(&path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                paths.push(entry.path());
+            }
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Removes the file or the folder, a missing one is not an error
+pub async fn remove(path: &Path) -> Result<(), Error> {
+    let res = match fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path).await,
+        Ok(_) => fs::remove_file(path).await,
+        Err(e) => Err(e),
+    };
+    match res {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Copies the folder with all its content
+pub async fn copy_dir(from: &Path, to: &Path) -> Result<(), Error> {
+    let mut dirs = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((from, to)) = dirs.pop() {
+        fs::create_dir_all(&to).await?;
+        let mut entries = fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let target = to.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                dirs.push((entry.path(), target));
+            } else {
+                fs::copy(entry.path(), target).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the file with a temporary one, a crash never leaves it half-written
+pub async fn write_atomic(path: &Path, contents: &str) -> Result<(), Error> {
+    write_file(path, contents, false).await
+}
+
+/// Like `write_atomic`, but only the owner can read the file
+pub async fn write_private(path: &Path, contents: &str) -> Result<(), Error> {
+    write_file(path, contents, true).await
+}
+
+async fn write_file(path: &Path, contents: &str, private: bool) -> Result<(), Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let mut file = options.open(&tmp_path).await?;
+    file.write_all(contents.as_bytes()).await?;
+    file.sync_all().await?;
+    drop(file);
+    if let Err(err) = fs::rename(&tmp_path, path).await {
+        fs::remove_file(&tmp_path).await.ok();
+        return Err(err.into());
+    }
+    // Makes the rename durable
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+/// A size in human-readable units, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
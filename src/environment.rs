@@ -0,0 +1,94 @@
+use anyhow::{anyhow as err, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+
+/// Variables of the launcher that the app inherits.
+///
+/// Patterns are names of variables and may end with `*`
+/// to match a prefix, e.g. `CARGO_*`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EnvFilter {
+    /// If not empty, only matching variables are inherited
+    pub allow: Vec<String>,
+    /// Matching variables are never inherited
+    pub deny: Vec<String>,
+}
+
+impl EnvFilter {
+    pub fn is_active(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|p| matches(p, name));
+        allowed && !self.deny.iter().any(|p| matches(p, name))
+    }
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+/// Parses a `KEY=VALUE` pair of the `--env` argument
+pub fn parse_var(pair: &str) -> Result<(String, String), Error> {
+    let (key, value) = pair
+        .split_once('=')
+        .ok_or_else(|| err!("Expected KEY=VALUE, got '{pair}'"))?;
+    if key.is_empty() {
+        return Err(err!("The name of the variable is empty in '{pair}'"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Builds the environment of the app.
+///
+/// Explicit variables are set regardless of the filter,
+/// the later ones override the earlier ones.
+/// Inherited variables may be not valid UTF-8 on Unix, they are kept as is.
+pub fn child_env<'a>(
+    filter: &EnvFilter,
+    explicit: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> BTreeMap<OsString, OsString> {
+    let mut vars: BTreeMap<OsString, OsString> = std::env::vars_os()
+        .filter(|(name, _)| filter.is_allowed(&name.to_string_lossy()))
+        .collect();
+    for (name, value) in explicit {
+        vars.insert(name.into(), value.into());
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_variables_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+        let value = OsString::from_vec(vec![b'a', 0xff, b'b']);
+        std::env::set_var("KNOWLEDGE_TEST_BINARY", &value);
+        let filter = EnvFilter {
+            allow: vec!["KNOWLEDGE_TEST_*".into()],
+            deny: Vec::new(),
+        };
+        let explicit = BTreeMap::from([("KNOWLEDGE_TEST_SET".to_string(), "1".to_string())]);
+
+        let vars = child_env(&filter, &explicit);
+
+        assert_eq!(
+            vars.get(&OsString::from("KNOWLEDGE_TEST_BINARY")),
+            Some(&value)
+        );
+        assert_eq!(
+            vars.get(&OsString::from("KNOWLEDGE_TEST_SET")),
+            Some(&"1".into())
+        );
+        assert!(!vars.contains_key(&OsString::from("PATH")));
+    }
+}
@@ -1,57 +1,216 @@
 use crate::app_info::AppInfo;
 use crate::built_info;
-use crate::RI_USER_AGENT;
-use anyhow::{anyhow as err, Error};
+use crate::credentials::TOKEN_VAR;
+use crate::http::{self, HttpClient, OfflineError, RateLimit, ResponseCache};
+use crate::preferences::Channel;
+use anyhow::{anyhow as err, Context, Error};
+use chrono::Local;
+use colored::Colorize;
 use futures::StreamExt;
 use indicatif::ProgressBar;
-use reqwest::header::{CONTENT_LENGTH, USER_AGENT};
-use reqwest::Client;
-use semver::Version;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{RequestBuilder, Url};
+use semver::{Version, VersionReq};
 use serde::Deserialize;
+use std::fmt;
 use tempfile::tempfile;
 use tokio::fs::File;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tokio::time::timeout;
+
+/// The token is only sent to the API, never to mirrors
+const API_HOST: &str = "api.github.com";
+const RATE_LIMIT_URL: &str = "https://api.github.com/rate_limit";
+/// Fewer requests left are reported to the user
+const LOW_LIMIT: u64 = 10;
 
 pub struct GitHubApi {
-    client: Client,
+    client: HttpClient,
+    channel: Channel,
+    releases_url: Option<String>,
+    version_limit: Option<VersionReq>,
+    token: Option<String>,
+    cache: Option<ResponseCache>,
+}
+
+impl Default for GitHubApi {
+    fn default() -> Self {
+        Self::new(HttpClient::default())
+    }
 }
 
 impl GitHubApi {
-    pub fn new() -> Self {
+    pub fn new(client: HttpClient) -> Self {
         Self {
-            client: Client::new(),
+            client,
+            channel: Channel::Stable,
+            releases_url: None,
+            version_limit: None,
+            token: None,
+            cache: None,
         }
     }
 
-    pub async fn releases(&mut self, url: &str) -> Result<Vec<Release>, Error> {
-        let releases = self
+    /// Sets releases that `latest_release` picks
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    /// Takes releases from a mirror instead of GitHub
+    pub fn set_releases_url(&mut self, url: Option<String>) {
+        self.releases_url = url;
+    }
+
+    /// Skips releases that don't meet the requirement
+    pub fn set_version_limit(&mut self, req: Option<VersionReq>) {
+        self.version_limit = req;
+    }
+
+    /// Authenticates requests to raise the limit of requests
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token;
+    }
+
+    /// Keeps the last known releases to revalidate them and to use them
+    /// if GitHub is not available
+    pub fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Requests left for the user, checking them doesn't count
+    pub async fn rate_limit(&self) -> Result<RateLimit, Error> {
+        let resp = self.client.send(|| self.request(RATE_LIMIT_URL)).await?;
+        RateLimit::from_headers(resp.headers())
+            .ok_or_else(|| Error::msg("GitHub didn't report the limit of requests"))
+    }
+
+    /// Releases of the app allowed by the version limit
+    pub async fn releases(&mut self, app_info: &AppInfo) -> Result<Vec<Release>, Error> {
+        let releases = match self.fetch_releases(app_info).await {
+            Ok(releases) => releases,
+            Err(err) => {
+                if let Some(limit) = RateLimit::exceeded_by(&err) {
+                    self.explain_limit(&limit);
+                } else if !http::is_network_error(&err) {
+                    return Err(err);
+                }
+                let Some(cache) = &self.cache else {
+                    return Err(err);
+                };
+                let Some((entry, body)) = cache.read(&cache_key(app_info)).await else {
+                    return Err(err);
+                };
+                // Working offline is reported by the caller
+                if !err.is::<OfflineError>() {
+                    let checked = entry.checked.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+                    let note = format!(
+                        "GitHub is not available, the releases known at {checked} are used"
+                    );
+                    println!("{}", note.dimmed());
+                }
+                parse_releases(&entry.url, &body)?
+            }
+        };
+        let limit = self.version_limit.as_ref();
+        Ok(releases
+            .into_iter()
+            .filter(|release| {
+                limit
+                    .map(|req| req.matches(&release.version))
+                    .unwrap_or(true)
+            })
+            .collect())
+    }
+
+    /// Tells when the limit resets and how to raise it
+    fn explain_limit(&self, limit: &RateLimit) {
+        let reset = limit.reset.with_timezone(&Local).format("%H:%M");
+        let mut warn = format!(
+            "The limit of {} requests to GitHub per hour is reached, it resets at {reset}.",
+            limit.limit
+        );
+        if self.token.is_none() {
+            warn.push_str(&format!(
+                "\nSet {TOKEN_VAR} or run `knowledge auth login` to raise the limit."
+            ));
+        }
+        println!("{}", warn.yellow());
+    }
+
+    async fn fetch_releases(&self, app_info: &AppInfo) -> Result<Vec<Release>, Error> {
+        let url = self.releases_url.as_deref().unwrap_or(app_info.link);
+        let key = cache_key(app_info);
+        let (body, headers) = self
             .client
-            .get(url)
-            .header(USER_AGENT, RI_USER_AGENT)
-            .send()
-            .await?
-            .json()
+            .get_cached(self.cache.as_ref(), &key, url, |url| self.request(url))
             .await?;
-        Ok(releases)
+        if let Some(limit) = RateLimit::from_headers(&headers) {
+            self.note_limit(&limit);
+        }
+        parse_releases(url, &body)
+    }
+
+    /// Warns about the limit before it's exhausted
+    fn note_limit(&self, limit: &RateLimit) {
+        if limit.remaining >= LOW_LIMIT {
+            return;
+        }
+        let reset = limit.reset.with_timezone(&Local).format("%H:%M");
+        let mut note = format!(
+            "{} requests to GitHub are left until {reset}",
+            limit.remaining
+        );
+        if self.token.is_none() {
+            note.push_str(&format!(", set {TOKEN_VAR} to raise the limit"));
+        }
+        println!("{}", note.dimmed());
+    }
+
+    /// A request with the token if it goes to the API of GitHub
+    fn request(&self, url: &str) -> RequestBuilder {
+        let request = self.client.get(url);
+        let to_api = Url::parse(url)
+            .map(|url| url.host_str() == Some(API_HOST))
+            .unwrap_or(false);
+        match &self.token {
+            Some(token) if to_api => request.bearer_auth(token),
+            _ => request,
+        }
     }
 
     pub async fn latest_release(&mut self, app_info: &AppInfo) -> Result<Release, Error> {
         let latest_release = self
-            .releases(&app_info.link)
+            .releases(app_info)
             .await?
             .into_iter()
-            .next()
+            .find(|release| self.channel == Channel::Beta || !release.prerelease)
             .ok_or_else(|| Error::msg("No releases available"))?;
         Ok(latest_release)
     }
 
+    /// The most recent release that meets the requirement
+    pub async fn matching_release(
+        &mut self,
+        app_info: &AppInfo,
+        req: &VersionReq,
+    ) -> Result<Release, Error> {
+        let release = self
+            .releases(app_info)
+            .await?
+            .into_iter()
+            .find(|release| req.matches(&release.version))
+            .ok_or_else(|| err!("No releases match the version {req}"))?;
+        Ok(release)
+    }
+
     pub async fn download_assets(&mut self, url: &str) -> Result<File, Error> {
-        let resp = self
-            .client
-            .get(url)
-            .header(USER_AGENT, RI_USER_AGENT)
-            .send()
-            .await?;
+        self.client
+            .retry("The download", || self.try_download(url))
+            .await
+    }
+
+    async fn try_download(&self, url: &str) -> Result<File, Error> {
+        let resp = self.client.download(url).await?;
         let total = resp
             .headers()
             .get(CONTENT_LENGTH)
@@ -61,7 +220,12 @@ impl GitHubApi {
         let mut chunks = resp.bytes_stream();
         let mut archive = File::from_std(tempfile()?);
         let bar = ProgressBar::new(total);
-        while let Some(chunk) = chunks.next().await.transpose()? {
+        let read_timeout = self.client.read_timeout();
+        while let Some(chunk) = timeout(read_timeout, chunks.next())
+            .await
+            .context("The download has stalled")?
+            .transpose()?
+        {
             bar.inc(chunk.len() as u64);
             archive.write_all(&chunk).await?;
         }
@@ -71,6 +235,14 @@ impl GitHubApi {
     }
 }
 
+fn cache_key(app_info: &AppInfo) -> String {
+    format!("releases-{}", app_info.name)
+}
+
+fn parse_releases(url: &str, body: &str) -> Result<Vec<Release>, Error> {
+    serde_json::from_str(body).map_err(|e| err!("Can't read releases from {url}: {e}"))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Release {
     /// Title
@@ -79,15 +251,14 @@ pub struct Release {
     /// IMPORTANT: `Tag` has to be a valid semver
     #[serde(rename = "tag_name")]
     pub version: Version,
+    #[serde(default)]
+    pub prerelease: bool,
     pub assets: Vec<Asset>,
 }
 
 impl Release {
     pub fn get_asset_for_os(&self, app_info: &AppInfo, os: &str) -> Result<&str, Error> {
-        let arch = built_info::CFG_TARGET_ARCH;
-        let ver = &self.version;
-        let name = &app_info.name;
-        let expected_asset = format!("{name}-{ver}-{os}-{arch}.tar.gz");
+        let expected_asset = asset_name(app_info, &self.version, os);
         for asset in &self.assets {
             if asset.name == expected_asset {
                 return Ok(&asset.browser_download_url);
@@ -95,6 +266,44 @@ impl Release {
         }
         Err(err!("Assets for '{os}' system was not found"))
     }
+
+    pub fn get_asset(&self, name: &str) -> Option<&str> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.as_str())
+    }
+}
+
+/// The archive of the app for the system, e.g. `ri-lab-1.0.0-linux-x86_64.tar.gz`
+pub fn asset_name(app_info: &AppInfo, version: &dyn fmt::Display, os: &str) -> String {
+    let arch = built_info::CFG_TARGET_ARCH;
+    format!("{}-{version}-{os}-{arch}.tar.gz", app_info.name)
+}
+
+/// The version of the archive made by `asset_name`
+pub fn parse_asset_name(app_info: &AppInfo, file_name: &str, os: &str) -> Option<Version> {
+    let arch = built_info::CFG_TARGET_ARCH;
+    file_name
+        .strip_prefix(app_info.name)?
+        .strip_prefix('-')?
+        .strip_suffix(&format!("-{os}-{arch}.tar.gz"))?
+        .parse()
+        .ok()
+}
+
+/// The template of a workspace for the course, e.g. `ri-lab-template-rust.tar.gz`
+pub fn template_name(app_info: &AppInfo, course: &str) -> String {
+    format!("{}-template-{course}.tar.gz", app_info.name)
+}
+
+/// The course of the template made by `template_name`
+pub fn parse_template_name<'a>(app_info: &AppInfo, file_name: &'a str) -> Option<&'a str> {
+    file_name
+        .strip_prefix(app_info.name)?
+        .strip_prefix("-template-")?
+        .strip_suffix(".tar.gz")
+        .filter(|course| !course.is_empty())
 }
 
 #[derive(Debug, Deserialize)]
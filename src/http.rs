@@ -0,0 +1,553 @@
+use crate::{built_info, disk, VERSION};
+use anyhow::{anyhow as err, Context, Error};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use futures::future;
+use reqwest::header::RETRY_AFTER;
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Certificate, Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::time::{error::Elapsed, sleep, timeout};
+
+/// The app is reached directly even behind a proxy
+const LOCAL_HOSTS: &str = "localhost,127.0.0.1,::1";
+/// The delay before the first retry, doubled for every next one
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// A longer `Retry-After` fails the request instead of waiting
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Well-known sites that tell the lack of the network from a failure of one host
+const PROBE_URLS: &[&str] = &["https://github.com/", "https://crates.io/"];
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Connections of the launcher to the internet
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Seconds to wait for a connection
+    pub connect_timeout: u64,
+    /// Seconds to wait for a response or the next part of a download
+    pub read_timeout: u64,
+    /// Attempts to repeat a request after a temporary failure
+    pub retries: u32,
+    /// A proxy for all requests, e.g. `http://proxy.example.com:3128`.
+    /// The variables `HTTPS_PROXY` and `ALL_PROXY` are used without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_password: Option<String>,
+    /// Hosts reached without the proxy, e.g. `.corp.example.com`
+    pub no_proxy: Vec<String>,
+    /// PEM files with extra trusted certificates, e.g. of a TLS-inspecting proxy
+    pub ca_certs: Vec<PathBuf>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            read_timeout: 30,
+            retries: 3,
+            proxy: None,
+            proxy_user: None,
+            proxy_password: None,
+            no_proxy: Vec::new(),
+            ca_certs: Vec::new(),
+        }
+    }
+}
+
+/// The client shared by all network requests of the launcher
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    read_timeout: Duration,
+    retries: u32,
+    /// Shared by all clones, no request is sent when it's set
+    offline: Arc<AtomicBool>,
+    /// Set once any site has responded, the network is not probed then
+    connected: Arc<AtomicBool>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(&NetworkConfig::default()).expect("the default client")
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: &NetworkConfig) -> Result<Self, Error> {
+        let mut builder = Client::builder()
+            .user_agent(user_agent())
+            .connect_timeout(Duration::from_secs(config.connect_timeout));
+        if let Some(url) = &config.proxy {
+            let mut proxy =
+                Proxy::all(url).map_err(|e| err!("Invalid proxy address '{url}': {e}"))?;
+            if let Some(user) = &config.proxy_user {
+                let password = config.proxy_password.as_deref().unwrap_or_default();
+                proxy = proxy.basic_auth(user, password);
+            }
+            let mut no_proxy = config.no_proxy.clone();
+            no_proxy.push(LOCAL_HOSTS.into());
+            proxy = proxy.no_proxy(NoProxy::from_string(&no_proxy.join(",")));
+            builder = builder.proxy(proxy);
+        }
+        for path in &config.ca_certs {
+            let pem = std::fs::read(path)
+                .map_err(|e| err!("Can't read certificates {}: {e}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| err!("Invalid certificates {}: {e}", path.display()))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(Self {
+            client: builder.build()?,
+            read_timeout: Duration::from_secs(config.read_timeout),
+            retries: config.retries,
+            offline: Arc::new(AtomicBool::new(false)),
+            connected: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// A request with a small response that has to arrive in time
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client.get(url).timeout(self.read_timeout)
+    }
+
+    /// Sends a request made by the closure until it succeeds or fails permanently
+    pub async fn send<F>(&self, request: F) -> Result<Response, Error>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.retry("The request", || async {
+            check_status(request().send().await?)
+        })
+        .await
+    }
+
+    /// Fetches the document, a saved copy is revalidated and downloaded again
+    /// only if it has changed. Returns the document and headers of the response.
+    pub async fn get_cached<F>(
+        &self,
+        cache: Option<&ResponseCache>,
+        key: &str,
+        url: &str,
+        request: F,
+    ) -> Result<(String, HeaderMap), Error>
+    where
+        F: Fn(&str) -> RequestBuilder,
+    {
+        let cached = match cache {
+            Some(cache) => cache.read(key).await,
+            None => None,
+        };
+        // A copy of another source can't be revalidated
+        let cached = cached.filter(|(entry, _body)| entry.url == url);
+        let resp = self
+            .send(|| {
+                let mut request = request(url);
+                if let Some((entry, _body)) = &cached {
+                    if let Some(etag) = &entry.etag {
+                        request = request.header(IF_NONE_MATCH, etag);
+                    }
+                    if let Some(last_modified) = &entry.last_modified {
+                        request = request.header(IF_MODIFIED_SINCE, last_modified);
+                    }
+                }
+                request
+            })
+            .await?;
+        let headers = resp.headers().clone();
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            let (Some(cache), Some((mut entry, body))) = (cache, cached) else {
+                return Err(err!("{url} responded with {}", resp.status()));
+            };
+            entry.checked = Utc::now();
+            // The copy is still valid if the time of the check is not saved
+            cache.write(key, &entry, None).await.ok();
+            return Ok((body, headers));
+        }
+        let body = resp.text().await?;
+        if let Some(cache) = cache {
+            let header = |name| {
+                let value = headers.get(name)?.to_str().ok()?;
+                Some(value.to_string())
+            };
+            let entry = CacheEntry {
+                url: url.into(),
+                etag: header(ETAG),
+                last_modified: header(LAST_MODIFIED),
+                checked: Utc::now(),
+            };
+            // The cache only saves requests, the document is fetched anyway
+            cache.write(key, &entry, Some(&body)).await.ok();
+        }
+        Ok((body, headers))
+    }
+
+    /// Starts a download of any length, only waiting for headers is limited
+    pub async fn download(&self, url: &str) -> Result<Response, Error> {
+        let resp = timeout(self.read_timeout, self.client.get(url).send())
+            .await
+            .with_context(|| format!("No response from {url}"))??;
+        check_status(resp)
+    }
+
+    /// Turns off all network requests
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+    }
+
+    /// Set by `set_offline` or after a failed connection
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    /// Checks any of well-known sites responds, with the proxy if it's set
+    async fn is_connected(&self) -> bool {
+        if self.connected.load(Ordering::Relaxed) {
+            return true;
+        }
+        let probes = PROBE_URLS
+            .iter()
+            .map(|url| Box::pin(self.client.head(*url).timeout(PROBE_TIMEOUT).send()));
+        let connected = future::select_ok(probes).await.is_ok();
+        self.connected.store(connected, Ordering::Relaxed);
+        connected
+    }
+
+    /// Repeats the idempotent operation while it fails temporarily.
+    ///
+    /// If a host can't be connected and well-known sites don't respond either,
+    /// there is no network and the client goes offline. A failure of a single
+    /// host, e.g. blocked by a firewall, is retried as any other one.
+    pub async fn retry<T, F, Fut>(&self, what: &str, mut operation: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            if self.is_offline() {
+                return Err(OfflineError.into());
+            }
+            let err = match operation().await {
+                Ok(value) => {
+                    self.connected.store(true, Ordering::Relaxed);
+                    return Ok(value);
+                }
+                Err(err) => err,
+            };
+            if is_unreachable(&err) && !self.is_connected().await {
+                self.set_offline(true);
+                return Err(err.context(OfflineError));
+            }
+            if attempt >= self.retries {
+                return Err(err);
+            }
+            let Some(delay) = retry_delay(&err, attempt) else {
+                return Err(err);
+            };
+            attempt += 1;
+            let message = format!(
+                "{what} failed: {err}\nRetrying in {}s (attempt {} of {})...",
+                delay.as_secs_f32().ceil(),
+                attempt + 1,
+                self.retries + 1
+            );
+            println!("{}", message.dimmed());
+            sleep(delay).await;
+        }
+    }
+
+    /// The longest pause between parts of a download
+    pub fn read_timeout(&self) -> Duration {
+        self.read_timeout
+    }
+}
+
+/// An unsuccessful status of a response
+#[derive(Debug)]
+pub struct StatusError {
+    pub url: String,
+    pub status: StatusCode,
+    /// The delay the server asked to wait before the next request
+    pub retry_after: Option<Duration>,
+    pub headers: HeaderMap,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} responded with {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Turns unsuccessful responses into errors
+pub fn check_status(resp: Response) -> Result<Response, Error> {
+    let status = resp.status();
+    // Only conditional requests get `304 Not Modified`
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(resp);
+    }
+    let retry_after = resp
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    Err(Error::new(StatusError {
+        url: resp.url().to_string(),
+        status,
+        retry_after,
+        headers: resp.headers().clone(),
+    }))
+}
+
+/// Requests left for the client, from `X-RateLimit-*` headers
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        let reset = number("x-ratelimit-reset")?;
+        Some(Self {
+            limit: number("x-ratelimit-limit")?,
+            remaining: number("x-ratelimit-remaining")?,
+            reset: DateTime::from_timestamp(i64::try_from(reset).ok()?, 0)?,
+        })
+    }
+
+    /// Checks the error is caused by the exhausted limit
+    pub fn exceeded_by(err: &Error) -> Option<Self> {
+        let status_err = err.downcast_ref::<StatusError>()?;
+        let status = status_err.status;
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+        Self::from_headers(&status_err.headers).filter(|limit| limit.remaining == 0)
+    }
+}
+
+/// Seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// The delay before the next attempt, `None` if the error is permanent
+fn retry_delay(err: &Error, attempt: u32) -> Option<Duration> {
+    let backoff = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY);
+    if let Some(status_err) = err.downcast_ref::<StatusError>() {
+        let status = status_err.status;
+        if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+            return None;
+        }
+        // Waiting for the reset of the limit takes too long
+        if RateLimit::from_headers(&status_err.headers).is_some_and(|limit| limit.remaining == 0) {
+            return None;
+        }
+        if let Some(delay) = status_err.retry_after {
+            return (delay <= MAX_RETRY_AFTER).then_some(delay);
+        }
+    } else if !is_network_error(err) {
+        return None;
+    }
+    Some(with_jitter(backoff))
+}
+
+/// Checks the server could not be reached or stopped responding
+pub fn is_network_error(err: &Error) -> bool {
+    if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
+        req_err.is_timeout() || req_err.is_connect() || req_err.is_request() || req_err.is_body()
+    } else {
+        err.is::<Elapsed>() || err.is::<OfflineError>()
+    }
+}
+
+/// The address is unknown or the connection is refused, a slow connection
+/// is not considered here
+fn is_unreachable(err: &Error) -> bool {
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(|req_err| req_err.is_connect() && !req_err.is_timeout())
+}
+
+/// Requests are not sent without the network
+#[derive(Debug)]
+pub struct OfflineError;
+
+impl fmt::Display for OfflineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The network is not available")
+    }
+}
+
+impl std::error::Error for OfflineError {}
+
+/// Spreads retries of many launchers in time, adds up to a half of the delay
+fn with_jitter(delay: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let fraction = f64::from(nanos % 1000) / 1000.0;
+    delay + delay.mul_f64(fraction / 2.0)
+}
+
+/// Responses kept on disk to revalidate them and to use them offline
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+/// Validators of a saved response
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CacheEntry {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// When the server confirmed the copy the last time
+    pub checked: DateTime<Utc>,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The saved copy, `None` if it's missing or unreadable
+    pub async fn read(&self, key: &str) -> Option<(CacheEntry, String)> {
+        let entry = fs::read_to_string(self.dir.join(format!("{key}.toml")))
+            .await
+            .ok()?;
+        let entry = toml::from_str(&entry).ok()?;
+        let body = fs::read_to_string(self.dir.join(format!("{key}.json")))
+            .await
+            .ok()?;
+        Some((entry, body))
+    }
+
+    /// Saves validators and the document if it has changed
+    async fn write(&self, key: &str, entry: &CacheEntry, body: Option<&str>) -> Result<(), Error> {
+        if let Some(body) = body {
+            disk::write_atomic(&self.dir.join(format!("{key}.json")), body).await?;
+        }
+        let entry = toml::to_string(entry)?;
+        disk::write_atomic(&self.dir.join(format!("{key}.toml")), &entry).await
+    }
+}
+
+/// Tells servers the version and the platform of the launcher
+pub fn user_agent() -> String {
+    format!(
+        "Rust Insight! Launcher/{} ({}; {}) (support@rustinsight.com)",
+        *VERSION,
+        built_info::CFG_OS,
+        built_info::CFG_TARGET_ARCH
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn status_error(status: u16, retry_after: Option<u64>, remaining: Option<u64>) -> Error {
+        let mut headers = HeaderMap::new();
+        if let Some(remaining) = remaining {
+            headers.insert("x-ratelimit-limit", HeaderValue::from(60));
+            headers.insert("x-ratelimit-remaining", HeaderValue::from(remaining));
+            headers.insert("x-ratelimit-reset", HeaderValue::from(1_900_000_000));
+        }
+        Error::new(StatusError {
+            url: "https://api.github.com/".into(),
+            status: StatusCode::from_u16(status).unwrap(),
+            retry_after: retry_after.map(Duration::from_secs),
+            headers,
+        })
+    }
+
+    fn assert_backoff(delay: Option<Duration>, secs: u64) {
+        let base = Duration::from_secs(secs);
+        let delay = delay.expect("the request is retried");
+        assert!(delay >= base && delay <= base + base / 2, "{delay:?}");
+    }
+
+    #[test]
+    fn retries_server_errors_with_backoff() {
+        assert_backoff(retry_delay(&status_error(500, None, None), 0), 1);
+        assert_backoff(retry_delay(&status_error(503, None, None), 2), 4);
+        assert_backoff(retry_delay(&status_error(502, None, None), 10), 30);
+        assert_backoff(retry_delay(&status_error(429, None, None), 1), 2);
+    }
+
+    #[test]
+    fn follows_retry_after() {
+        let delay = retry_delay(&status_error(429, Some(5), None), 0);
+        assert_eq!(delay, Some(Duration::from_secs(5)));
+        let delay = retry_delay(&status_error(503, Some(0), None), 3);
+        assert_eq!(delay, Some(Duration::ZERO));
+        // Too long to wait
+        assert_eq!(retry_delay(&status_error(503, Some(120), None), 0), None);
+    }
+
+    #[test]
+    fn fails_permanent_errors() {
+        assert_eq!(retry_delay(&status_error(404, None, None), 0), None);
+        assert_eq!(retry_delay(&status_error(401, None, None), 0), None);
+        assert_eq!(retry_delay(&Error::msg("invalid JSON"), 0), None);
+    }
+
+    #[test]
+    fn stops_at_the_exhausted_rate_limit() {
+        let err = status_error(403, None, Some(0));
+        assert_eq!(retry_delay(&err, 0), None);
+        assert_eq!(RateLimit::exceeded_by(&err).unwrap().limit, 60);
+        let err = status_error(429, Some(5), Some(0));
+        assert_eq!(retry_delay(&err, 0), None);
+        // Requests are left, the server failed
+        let err = status_error(500, None, Some(10));
+        assert_backoff(retry_delay(&err, 0), 1);
+        assert!(RateLimit::exceeded_by(&err).is_none());
+        assert!(RateLimit::exceeded_by(&status_error(403, None, Some(10))).is_none());
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 7 "), Some(Duration::from_secs(7)));
+        let past = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(parse_retry_after(past), Some(Duration::ZERO));
+        let future = (Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let delay = parse_retry_after(&future).unwrap();
+        assert!(delay > Duration::from_secs(80) && delay <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::opts::LearnCommand;
+use crate::{disk, process};
+use anyhow::{anyhow as err, Error};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// The app instance spawned by the launcher
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Instance {
+    pub id: u32,
+    pub pid: u32,
+    /// Identifies the process if the `pid` is reused
+    #[serde(default)]
+    pub start_time: Option<u64>,
+    pub port: u16,
+    pub workdir: PathBuf,
+    pub version: Option<Version>,
+    pub started: DateTime<Utc>,
+    /// Another process has asked the app to stop
+    #[serde(default)]
+    pub stopping: bool,
+    #[serde(default)]
+    pub options: LaunchOptions,
+    /// The log of the app, it's not removed while the instance is running
+    #[serde(default)]
+    pub session: Option<u32>,
+}
+
+/// Options of the command line the instance was launched with, reused on restart
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LaunchOptions {
+    pub port: Option<u16>,
+    pub restart: bool,
+    pub attach: bool,
+    pub envs: Vec<(String, String)>,
+    pub args: Vec<String>,
+}
+
+impl LaunchOptions {
+    pub fn to_command(&self, dir: PathBuf) -> LearnCommand {
+        LearnCommand {
+            dir: Some(dir),
+            port: self.port,
+            restart: self.restart,
+            attach: self.attach,
+            envs: self.envs.clone(),
+            args: self.args.clone(),
+        }
+    }
+}
+
+impl From<&LearnCommand> for LaunchOptions {
+    fn from(opts: &LearnCommand) -> Self {
+        Self {
+            port: opts.port,
+            restart: opts.restart,
+            attach: opts.attach,
+            envs: opts.envs.clone(),
+            args: opts.args.clone(),
+        }
+    }
+}
+
+impl Instance {
+    /// Checks the process of the app is alive and it's not another one with the same `pid`
+    pub fn is_running(&self) -> bool {
+        match self.start_time {
+            Some(start_time) => process::start_time(self.pid) == Some(start_time),
+            None => process::is_alive(self.pid),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://localhost:{}/", self.port)
+    }
+}
+
+/// Keeps a file per every running instance of the app
+#[derive(Debug)]
+pub struct Registry {
+    dir: PathBuf,
+}
+
+impl Registry {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn instance_path(&self, id: u32) -> PathBuf {
+        let mut path = self.dir.clone();
+        path.push(format!("{id}.toml"));
+        path
+    }
+
+    async fn read(path: &Path) -> Result<Option<Instance>, Error> {
+        match fs::read_to_string(path).await {
+            Ok(contents) => Ok(Some(toml::from_str(&contents)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn remove(path: &Path) -> Result<(), Error> {
+        match fs::remove_file(path).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns running instances ordered by id and drops entries of exited processes.
+    ///
+    /// Unreadable entries are skipped, another launcher could be creating them.
+    pub async fn list(&self) -> Result<Vec<Instance>, Error> {
+        let mut instances = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            match Self::read(&path).await {
+                Ok(Some(instance)) if instance.is_running() => instances.push(instance),
+                Ok(Some(_exited)) => Self::remove(&path).await?,
+                Ok(None) | Err(_) => {}
+            }
+        }
+        instances.sort_by_key(|instance| instance.id);
+        Ok(instances)
+    }
+
+    pub async fn get(&self, id: u32) -> Result<Instance, Error> {
+        self.list()
+            .await?
+            .into_iter()
+            .find(|instance| instance.id == id)
+            .ok_or_else(|| err!("The instance {id} is not running"))
+    }
+
+    pub async fn find_by_workdir(&self, workdir: &Path) -> Result<Option<Instance>, Error> {
+        let instance = self
+            .list()
+            .await?
+            .into_iter()
+            .find(|instance| instance.workdir == workdir);
+        Ok(instance)
+    }
+
+    pub async fn register(
+        &self,
+        pid: u32,
+        port: u16,
+        workdir: PathBuf,
+        version: Option<Version>,
+        options: LaunchOptions,
+        session: Option<u32>,
+    ) -> Result<Instance, Error> {
+        let instances = self.list().await?;
+        let mut instance = Instance {
+            id: 0,
+            pid,
+            start_time: process::start_time(pid),
+            port,
+            workdir,
+            version,
+            started: Utc::now(),
+            stopping: false,
+            options,
+            session,
+        };
+        // Takes the smallest free id to keep them short,
+        // the id may be taken by another launcher at the same moment
+        for id in 1.. {
+            if instances.iter().any(|instance| instance.id == id) {
+                continue;
+            }
+            instance.id = id;
+            let res = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.instance_path(id))
+                .await;
+            let mut file = match res {
+                Ok(file) => file,
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let contents = toml::to_string(&instance)?;
+            file.write_all(contents.as_bytes()).await?;
+            file.sync_all().await?;
+            break;
+        }
+        Ok(instance)
+    }
+
+    async fn write(&self, instance: &Instance) -> Result<(), Error> {
+        let contents = toml::to_string(instance)?;
+        disk::write_atomic(&self.instance_path(instance.id), &contents).await
+    }
+
+    /// Marks the instance as stopped on purpose before it's terminated,
+    /// that lets the supervising launcher not to treat the exit as a crash.
+    pub async fn request_stop(&self, instance: &Instance) -> Result<(), Error> {
+        let path = self.instance_path(instance.id);
+        if let Some(mut stored) = Self::read(&path).await? {
+            if stored.pid == instance.pid {
+                stored.stopping = true;
+                self.write(&stored).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the instance was stopped by another process:
+    /// the entry was removed or marked as stopping.
+    pub async fn is_stop_requested(&self, instance: &Instance) -> Result<bool, Error> {
+        let path = self.instance_path(instance.id);
+        let stopped = match Self::read(&path).await? {
+            Some(stored) => stored.pid != instance.pid || stored.stopping,
+            None => true,
+        };
+        Ok(stopped)
+    }
+
+    /// Removes the entry if it still belongs to the same process
+    pub async fn unregister(&self, instance: &Instance) -> Result<(), Error> {
+        let path = self.instance_path(instance.id);
+        if let Some(stored) = Self::read(&path).await? {
+            if stored.pid == instance.pid {
+                Self::remove(&path).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn registry(dir: &TempDir) -> Registry {
+        Registry::new(dir.path().to_path_buf())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn drops_an_entry_of_a_reused_pid() {
+        let dir = TempDir::new().unwrap();
+        let registry = registry(&dir);
+        let pid = std::process::id();
+        let instance = registry
+            .register(
+                pid,
+                6361,
+                PathBuf::from("/tmp"),
+                None,
+                LaunchOptions::default(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(registry.list().await.unwrap().len(), 1);
+
+        // The same pid, but another process, e.g. after a reboot
+        let mut reused = instance.clone();
+        reused.start_time = instance.start_time.map(|time| time + 1);
+        registry.write(&reused).await.unwrap();
+
+        assert!(!reused.is_running());
+        assert!(registry.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_unreadable_entries() {
+        let dir = TempDir::new().unwrap();
+        let registry = registry(&dir);
+        // Another launcher is writing the entry
+        let path = registry.instance_path(1);
+        fs::write(&path, "id = 1\npid =").await.unwrap();
+
+        assert!(registry.list().await.unwrap().is_empty());
+        assert!(path.exists());
+        let instance = registry
+            .register(
+                std::process::id(),
+                6361,
+                PathBuf::from("/tmp"),
+                None,
+                LaunchOptions::default(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(instance.id, 2);
+    }
+}
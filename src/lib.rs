@@ -2,15 +2,28 @@ pub mod app;
 pub mod app_info;
 pub mod cacher;
 pub mod crates;
+pub mod credentials;
+pub mod disk;
+pub mod environment;
 pub mod github;
+pub mod http;
+pub mod instance;
+pub mod lock;
+pub mod logs;
 pub mod opts;
+pub mod policy;
+pub mod preferences;
 pub mod probe;
+pub mod process;
+pub mod project;
+pub mod retention;
+pub mod snapshot;
+pub mod supervisor;
+pub mod workspace;
 
 use once_cell::sync::Lazy;
 use semver::Version;
 
-pub const RI_USER_AGENT: &str = "Rust Insight! Launcher (support@rustinsight.com)";
-
 pub static VERSION: Lazy<Version> = Lazy::new(|| built_info::PKG_VERSION.parse().unwrap());
 
 pub mod built_info {
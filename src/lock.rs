@@ -0,0 +1,59 @@
+use anyhow::{anyhow as err, Error};
+use colored::Colorize;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use tokio::time::{sleep, Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Long enough for another launcher to download the app
+const LOCK_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// For reading the cache, many launchers may hold it
+    Shared,
+    /// For changing the cache, only one launcher may hold it
+    Exclusive,
+}
+
+/// An advisory lock of the launcher's files, released on drop
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Waits for other launchers to release the lock
+    pub async fn acquire(path: &Path, mode: LockMode) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        let started = Instant::now();
+        let mut notified = false;
+        loop {
+            let res = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+            match res {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+            if started.elapsed() >= LOCK_TIMEOUT {
+                return Err(err!(
+                    "Another launcher holds {} for too long, try again later",
+                    path.display()
+                ));
+            }
+            if !notified {
+                let message = "Waiting for another launcher to finish updating...";
+                println!("{}", message.dimmed());
+                notified = true;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::disk;
+use crate::supervisor::OutputTail;
+use anyhow::{anyhow as err, Error};
+use chrono::{Local, SecondsFormat};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+const PREFIX: &str = "session-";
+const EXT: &str = "log";
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    /// Maximal size of a log file in bytes before it's rotated
+    pub max_size: u64,
+    /// How many rotated files are kept for a session
+    pub max_parts: u32,
+    /// How many sessions are kept
+    pub max_sessions: usize,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 5 * 1024 * 1024,
+            max_parts: 3,
+            max_sessions: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn tag(&self) -> &'static str {
+        match self {
+            Self::Stdout => "out",
+            Self::Stderr => "err",
+        }
+    }
+}
+
+/// Log files of the app sessions
+#[derive(Debug)]
+pub struct LogStore {
+    dir: PathBuf,
+}
+
+impl LogStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn session_path(&self, id: u32) -> PathBuf {
+        let mut path = self.dir.clone();
+        path.push(format!("{PREFIX}{id}.{EXT}"));
+        path
+    }
+
+    /// Returns ids of all stored sessions in ascending order
+    pub async fn sessions(&self) -> Result<Vec<u32>, Error> {
+        let mut sessions = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(PREFIX))
+                .and_then(|name| name.strip_suffix(&format!(".{EXT}")))
+                .and_then(|id| id.parse().ok());
+            if let Some(id) = id {
+                sessions.push(id);
+            }
+        }
+        sessions.sort_unstable();
+        Ok(sessions)
+    }
+
+    /// Returns files of the session from the oldest to the current one
+    pub async fn session_files(&self, id: u32) -> Result<Vec<PathBuf>, Error> {
+        let path = self.session_path(id);
+        let mut files = Vec::new();
+        let mut part = 1;
+        loop {
+            let rotated = rotated_path(&path, part);
+            if !fs::try_exists(&rotated).await? {
+                break;
+            }
+            files.insert(0, rotated);
+            part += 1;
+        }
+        files.push(path);
+        Ok(files)
+    }
+
+    /// Starts a new session and removes the oldest ones.
+    ///
+    /// The `active` sessions are still written by running instances, they are kept.
+    pub async fn create_session(
+        &self,
+        config: &LogsConfig,
+        active: &[u32],
+    ) -> Result<SessionLog, Error> {
+        let sessions = self.sessions().await?;
+        let keep = config.max_sessions.saturating_sub(1);
+        let outdated = sessions.len().saturating_sub(keep);
+        for old in sessions[..outdated]
+            .iter()
+            .filter(|id| !active.contains(id))
+        {
+            for file in self.session_files(*old).await? {
+                disk::remove(&file).await?;
+            }
+        }
+        // Another launcher may take the same id at the same moment
+        let mut id = sessions.last().copied().unwrap_or_default();
+        let (id, path, file) = loop {
+            id += 1;
+            let path = self.session_path(id);
+            let res = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await;
+            match res {
+                Ok(file) => break (id, path, file),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        };
+        let writer = LogWriter {
+            path,
+            file,
+            size: 0,
+            max_size: config.max_size,
+            max_parts: config.max_parts,
+        };
+        Ok(SessionLog {
+            id,
+            writer: Arc::new(Mutex::new(writer)),
+        })
+    }
+}
+
+fn rotated_path(path: &Path, part: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{part}"));
+    PathBuf::from(name)
+}
+
+#[derive(Debug)]
+struct LogWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_parts: u32,
+}
+
+impl LogWriter {
+    async fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate().await?;
+        }
+        self.file.write_all(line.as_bytes()).await?;
+        self.file.write_all(b"\n").await?;
+        self.file.flush().await?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> Result<(), Error> {
+        if self.max_parts == 0 {
+            self.file.set_len(0).await?;
+            self.file.seek(std::io::SeekFrom::Start(0)).await?;
+        } else {
+            for part in (1..self.max_parts).rev() {
+                let from = rotated_path(&self.path, part);
+                if fs::try_exists(&from).await? {
+                    fs::rename(from, rotated_path(&self.path, part + 1)).await?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1)).await?;
+            self.file = File::create(&self.path).await?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// The log file of a single launch of the app
+#[derive(Debug, Clone)]
+pub struct SessionLog {
+    pub id: u32,
+    writer: Arc<Mutex<LogWriter>>,
+}
+
+impl SessionLog {
+    pub async fn path(&self) -> PathBuf {
+        self.writer.lock().await.path.clone()
+    }
+
+    /// Writes a line of the launcher itself
+    pub async fn note(&self, message: &str) -> Result<(), Error> {
+        let time = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        let line = format!("{time} [launcher] {message}");
+        self.writer.lock().await.write_line(&line).await
+    }
+
+    async fn write(&self, stream: Stream, message: &str) -> Result<(), Error> {
+        let time = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        let line = format!("{time} [{}] {message}", stream.tag());
+        self.writer.lock().await.write_line(&line).await
+    }
+}
+
+/// Routes the output of the app to the log, the terminal and the crash report
+#[derive(Debug, Clone)]
+pub struct OutputPump {
+    pub log: SessionLog,
+    pub echo: bool,
+    pub tail: OutputTail,
+}
+
+impl OutputPump {
+    pub fn spawn<R>(&self, reader: R, stream: Stream) -> JoinHandle<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let pump = self.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(reader);
+            let mut buf = Vec::new();
+            // Reads bytes to not stop on the first invalid UTF-8 line,
+            // the app would block or fail writing to the closed pipe.
+            while let Ok(1..) = reader.read_until(b'\n', &mut buf).await {
+                let line = String::from_utf8_lossy(&buf);
+                let line = line.trim_end_matches(['\n', '\r']).to_string();
+                buf.clear();
+                pump.log.write(stream, &line).await.ok();
+                if pump.echo {
+                    let prefix = "ri-lab |".truecolor(255, 61, 0);
+                    match stream {
+                        Stream::Stdout => println!("{prefix} {line}"),
+                        Stream::Stderr => println!("{prefix} {}", line.yellow()),
+                    }
+                }
+                if stream == Stream::Stderr {
+                    pump.tail.push(line);
+                }
+            }
+        })
+    }
+}
+
+/// Prints the session log and optionally waits for new lines
+pub async fn show(store: &LogStore, session: Option<u32>, follow: bool) -> Result<(), Error> {
+    let sessions = store.sessions().await?;
+    let id = match session {
+        Some(id) if sessions.contains(&id) => id,
+        Some(id) => return Err(err!("The session {id} is not available")),
+        None => *sessions
+            .last()
+            .ok_or_else(|| Error::msg("No logs available yet"))?,
+    };
+    let files = store.session_files(id).await?;
+    let mut stdout = tokio::io::stdout();
+    for file in &files {
+        match fs::read(file).await {
+            Ok(contents) => stdout.write_all(&contents).await?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    stdout.flush().await?;
+    if follow {
+        let path = store.session_path(id);
+        let mut offset = fs::metadata(&path).await?.len();
+        loop {
+            sleep(Duration::from_millis(500)).await;
+            let len = match fs::metadata(&path).await {
+                Ok(meta) => meta.len(),
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if len < offset {
+                // The file was rotated
+                offset = 0;
+            }
+            if len > offset {
+                let mut file = File::open(&path).await?;
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+                let mut chunk = Vec::new();
+                file.read_to_end(&mut chunk).await?;
+                offset += chunk.len() as u64;
+                stdout.write_all(&chunk).await?;
+                stdout.flush().await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn keeps_sessions_of_running_instances() {
+        let dir = TempDir::new().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        let config = LogsConfig {
+            max_sessions: 2,
+            ..LogsConfig::default()
+        };
+        for id in 1..=3 {
+            fs::write(store.session_path(id), "").await.unwrap();
+        }
+
+        let log = store.create_session(&config, &[1]).await.unwrap();
+
+        assert_eq!(log.id, 4);
+        assert_eq!(store.sessions().await.unwrap(), vec![1, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn takes_another_id_of_a_concurrent_session() {
+        let dir = TempDir::new().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        let config = LogsConfig::default();
+
+        let first = store.create_session(&config, &[]).await.unwrap();
+        first.note("started").await.unwrap();
+        let second = store.create_session(&config, &[]).await.unwrap();
+
+        assert_eq!((first.id, second.id), (1, 2));
+        let contents = fs::read_to_string(first.path().await).await.unwrap();
+        assert!(contents.contains("started"));
+    }
+}
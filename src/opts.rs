@@ -1,20 +1,58 @@
+use crate::environment;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Opts {
+    /// Keep all folders of the launcher in this folder (or set KNOWLEDGE_HOME)
+    #[clap(long, global = true, value_name = "PATH")]
+    pub home: Option<PathBuf>,
+    /// Use a separate config, state and versions (or set KNOWLEDGE_PROFILE)
+    #[clap(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Don't connect to the network, use installed versions
+    #[clap(long, global = true)]
+    pub offline: bool,
+    /// Install the app for all users of the machine, requires an administrator
+    #[clap(long, global = true)]
+    pub system_install: bool,
     #[command(subcommand)]
     pub command: Option<AppCommand>,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum AppCommand {
-    /// Removes the cache folder
-    Clean,
+    /// Removes downloaded files and the state of the launcher
+    Clean(CleanCommand),
+    /// Removes all folders of the launcher
+    Uninstall(UninstallCommand),
+    /// Shows the disk usage and removes old versions
+    Cache(CacheCommand),
+    /// Shows and changes settings of the launcher
+    Config(ConfigCommand),
+    /// Manages profiles with separate settings and versions
+    Profile(ProfileCommand),
+    /// Manages the token of GitHub
+    Auth(AuthCommand),
+    /// Installs the app and templates from downloaded release assets
+    Import(ImportCommand),
     /// Launches the Ri! Learn app
-    Learn,
+    Learn(LearnCommand),
+    /// Creates a practice workspace
+    Init(InitCommand),
     /// Updates the launcher and apps
     Update(UpdateCommand),
+    /// Lists running instances of the app
+    Ps,
+    /// Stops running instances of the app
+    Stop(StopCommand),
+    /// Restarts an instance of the app in this terminal
+    Restart(RestartCommand),
+    /// Shows logs of the app
+    Logs(LogsCommand),
+    /// Manages snapshots of the working folder
+    Snapshot(SnapshotCommand),
     /*
     /// Opens a link to the latest Stack version
     Stack,
@@ -23,10 +61,177 @@ pub enum AppCommand {
 
 impl Default for AppCommand {
     fn default() -> Self {
-        Self::Learn
+        Self::Learn(LearnCommand::default())
     }
 }
 
+#[derive(Debug, Parser, Clone, Default)]
+pub struct LearnCommand {
+    /// Working folder of the app (the current one by default)
+    #[clap(long, short)]
+    pub dir: Option<PathBuf>,
+    /// Port of the app (the first free one by default)
+    #[clap(long, short)]
+    pub port: Option<u16>,
+    /// Restart the app automatically if it crashes or hangs
+    #[clap(long)]
+    pub restart: bool,
+    /// Print the output of the app to the terminal
+    #[clap(long, short, visible_alias = "verbose")]
+    pub attach: bool,
+    /// Set an environment variable for the app
+    #[clap(long = "env", short, value_name = "KEY=VALUE", value_parser = environment::parse_var)]
+    pub envs: Vec<(String, String)>,
+    /// Extra arguments passed to the app as is
+    #[clap(last = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct InitCommand {
+    /// The course to take a template for
+    pub course: Option<String>,
+    /// Folder of the workspace (named after the course by default)
+    pub dir: Option<PathBuf>,
+    /// Launch the app in the workspace without asking
+    #[clap(long, short)]
+    pub launch: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CleanCommand {
+    /// Remove binaries of the app (all the cache if nothing is selected)
+    #[clap(long)]
+    pub binaries: bool,
+    /// Remove downloaded templates
+    #[clap(long)]
+    pub downloads: bool,
+    /// Remove binaries of versions that are not used anymore
+    #[clap(long)]
+    pub old_versions: bool,
+    /// Remove recent folders, installed versions and logs
+    #[clap(long)]
+    pub state: bool,
+    /// Only show what would be removed
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Don't ask for a confirmation
+    #[clap(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct UninstallCommand {
+    /// Only show what would be removed
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Don't ask for a confirmation
+    #[clap(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CacheCommand {
+    #[command(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum CacheAction {
+    /// Shows the disk usage of products and versions
+    Info,
+    /// Removes files outside the retention policy
+    Gc(CacheGc),
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CacheGc {
+    /// Only show what would be removed
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ConfigCommand {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum ConfigAction {
+    /// Prints the value of a setting
+    Get {
+        /// Dotted name of the setting, e.g. `preferences.port`
+        key: String,
+    },
+    /// Changes a setting
+    Set {
+        /// Dotted name of the setting, e.g. `preferences.port`
+        key: String,
+        /// A value in the TOML syntax, strings may be unquoted
+        value: String,
+    },
+    /// Resets a setting to the default
+    Unset {
+        /// Dotted name of the setting, e.g. `preferences.port`
+        key: String,
+    },
+    /// Prints all settings
+    List,
+    /// Opens the config file in the editor
+    Edit,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ProfileCommand {
+    #[command(subcommand)]
+    pub action: ProfileAction,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum ProfileAction {
+    /// Prints all profiles
+    List,
+    /// Creates a profile with the default settings
+    Create { name: String },
+    /// Removes a profile with its settings, versions and logs
+    Delete {
+        name: String,
+        /// Don't ask for a confirmation
+        #[clap(long, short)]
+        yes: bool,
+    },
+    /// Creates a profile with settings and the version of another one
+    Copy { from: String, to: String },
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct AuthCommand {
+    #[command(subcommand)]
+    pub action: AuthAction,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum AuthAction {
+    /// Stores a token of GitHub readable only by the user
+    Login {
+        /// The token, it's asked or read from the input if not set
+        #[clap(long)]
+        token: Option<String>,
+    },
+    /// Removes the stored token
+    Logout,
+    /// Shows the token in use and requests left
+    Status,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportCommand {
+    /// The archive of the app, e.g. `ri-lab-1.0.0-linux-x86_64.tar.gz`,
+    /// a template of a course or a folder with them
+    pub path: PathBuf,
+}
+
 #[derive(Debug, Parser, Clone)]
 pub struct UpdateCommand {
     /// Override an operating system of downloading assets
@@ -36,3 +241,65 @@ pub struct UpdateCommand {
     #[clap(long, short)]
     pub force: bool,
 }
+
+#[derive(Debug, Parser, Clone)]
+pub struct StopCommand {
+    /// Id of the instance (see the `ps` command)
+    #[clap(required_unless_present = "all", conflicts_with = "all")]
+    pub id: Option<u32>,
+    /// Stop all running instances
+    #[clap(long, short)]
+    pub all: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct RestartCommand {
+    /// Id of the instance (see the `ps` command)
+    pub id: u32,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct LogsCommand {
+    /// Wait for new lines
+    #[clap(long, short)]
+    pub follow: bool,
+    /// Id of the session (the latest by default)
+    #[clap(long, short)]
+    pub session: Option<u32>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SnapshotCommand {
+    /// Working folder (the current one by default)
+    #[clap(long, short, global = true)]
+    pub dir: Option<PathBuf>,
+    #[command(subcommand)]
+    pub action: SnapshotAction,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum SnapshotAction {
+    /// Lists snapshots of the folder
+    List,
+    /// Takes a snapshot of the folder
+    Create,
+    /// Brings the folder back to the snapshot
+    Restore(SnapshotRestore),
+    /// Shows files changed since the snapshot
+    Diff(SnapshotDiff),
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SnapshotRestore {
+    /// Id of the snapshot (see `snapshot list`)
+    pub id: u32,
+    /// Don't ask for a confirmation
+    #[clap(long, short)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SnapshotDiff {
+    /// Id of the snapshot (see `snapshot list`)
+    pub id: u32,
+}
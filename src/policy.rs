@@ -0,0 +1,167 @@
+use anyhow::{anyhow as err, Error};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use toml::{Table, Value};
+
+/// Rules of an organisation for all users of the machine.
+///
+/// The file is managed by administrators and is never written by the launcher.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    /// Versions of the app that may be installed, e.g. `=0.9.0` or `<1.0`
+    pub version: Option<VersionReq>,
+    /// Tell about new versions of the launcher
+    pub update_notices: bool,
+    /// Allow the `clean`, `cache gc` and `uninstall` commands
+    pub allow_clean: bool,
+    /// Settings that users can't change, the same layout as the user config
+    pub settings: Table,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            version: None,
+            update_notices: true,
+            allow_clean: true,
+            settings: Table::new(),
+        }
+    }
+}
+
+impl Policy {
+    /// The policy of the machine, the default one if there is no file
+    pub async fn load(path: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(path).await {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| err!("Can't read the policy {}: {e}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Checks the version of the app is allowed
+    pub fn allows(&self, version: &Version) -> bool {
+        self.version
+            .as_ref()
+            .map(|req| req.matches(version))
+            .unwrap_or(true)
+    }
+
+    /// Overrides settings of the user with the locked ones
+    pub fn apply(&self, config: &mut Table) {
+        merge(config, &self.settings);
+    }
+
+    /// Checks the dotted key of a setting is locked
+    pub fn is_locked(&self, key: &str) -> bool {
+        let mut table = &self.settings;
+        let mut parts = key.split('.').peekable();
+        while let Some(part) = parts.next() {
+            match table.get(part) {
+                Some(Value::Table(inner)) if parts.peek().is_some() => table = inner,
+                Some(_) => return true,
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+fn merge(target: &mut Table, overrides: &Table) {
+    for (name, value) in overrides {
+        match (target.get_mut(name), value) {
+            (Some(Value::Table(inner)), Value::Table(overrides)) => merge(inner, overrides),
+            _ => {
+                target.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The place of the policy file
+pub fn path() -> PathBuf {
+    if cfg!(windows) {
+        let program_data =
+            std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+        Path::new(&program_data)
+            .join("knowledge")
+            .join("policy.toml")
+    } else {
+        PathBuf::from("/etc/knowledge/policy.toml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+version = "<2.0"
+
+[settings.preferences]
+channel = "stable"
+
+[settings.global.retention]
+keep_versions = 1
+"#;
+
+    fn policy() -> Policy {
+        toml::from_str(POLICY).unwrap()
+    }
+
+    #[test]
+    fn finds_locked_keys() {
+        let policy = policy();
+        assert!(policy.is_locked("preferences.channel"));
+        assert!(policy.is_locked("global.retention.keep_versions"));
+        // A value inside a locked one
+        assert!(policy.is_locked("preferences.channel.name"));
+        assert!(!policy.is_locked("preferences.port"));
+        assert!(!policy.is_locked("global.retention.pinned"));
+        assert!(!policy.is_locked("global.network.proxy"));
+        assert!(!policy.is_locked("unknown"));
+    }
+
+    #[test]
+    fn overrides_only_locked_settings() {
+        let mut config: Table = toml::from_str(
+            r#"
+[preferences]
+channel = "beta"
+port = 7000
+
+[global]
+retention = "broken"
+"#,
+        )
+        .unwrap();
+
+        policy().apply(&mut config);
+
+        let expected: Table = toml::from_str(
+            r#"
+[preferences]
+channel = "stable"
+port = 7000
+
+[global.retention]
+keep_versions = 1
+"#,
+        )
+        .unwrap();
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn limits_versions() {
+        let policy = policy();
+        assert!(policy.allows(&"1.9.0".parse().unwrap()));
+        assert!(!policy.allows(&"2.0.0".parse().unwrap()));
+        assert!(Policy::default().allows(&"2.0.0".parse().unwrap()));
+    }
+}
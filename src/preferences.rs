@@ -0,0 +1,252 @@
+use crate::cacher::UserConfig;
+use anyhow::{anyhow as err, Error};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, Item};
+
+/// Ten years, longer intervals are surely a mistake
+const MAX_UPDATE_INTERVAL: u64 = 10 * 365 * 24;
+
+/// Settings of the launcher tuned by the user
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Hours between checks for updates
+    pub update_interval: u64,
+    /// Check and install updates of the app on launch
+    pub auto_update: bool,
+    /// The port of the app unless a project or the command line sets it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Open the app in the browser when it's started
+    pub open_browser: bool,
+    pub channel: Channel,
+    /// A mirror of the releases API of the app
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_url: Option<String>,
+    pub color: ColorMode,
+    /// A token of GitHub to raise the limit of requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_token: Option<String>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            update_interval: 24,
+            auto_update: true,
+            port: None,
+            open_browser: true,
+            channel: Channel::Stable,
+            release_url: None,
+            color: ColorMode::Auto,
+            github_token: None,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn update_interval(&self) -> Duration {
+        Duration::from_secs(self.update_interval.saturating_mul(60 * 60))
+    }
+
+    /// Checks values are within their ranges
+    pub fn check(&self) -> Result<(), Error> {
+        if self.update_interval > MAX_UPDATE_INTERVAL {
+            return Err(err!(
+                "preferences.update_interval is {} hours, the maximum is {MAX_UPDATE_INTERVAL}",
+                self.update_interval
+            ));
+        }
+        Ok(())
+    }
+
+    /// Turns colors of the output on or off
+    pub fn apply_color(&self) {
+        match self.color {
+            ColorMode::Auto => {}
+            ColorMode::Always => colored::control::set_override(true),
+            ColorMode::Never => colored::control::set_override(false),
+        }
+    }
+}
+
+/// Releases the app is updated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Stable,
+    /// Pre-releases too
+    Beta,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Variables of the app are set as `env.NAME`
+const ENV_PREFIX: &str = "env.";
+
+/// Every setting with a value, to know keys and types of settings
+fn template() -> Result<Table, Error> {
+    let mut config = UserConfig::default();
+    // Empty options are not serialized
+    config.preferences.port = Some(0);
+    config.preferences.release_url = Some(String::new());
+    config.preferences.github_token = Some(String::new());
+    config.global.retention.max_cache_size = Some(0);
+    config.global.network.proxy = Some(String::new());
+    config.global.network.proxy_user = Some(String::new());
+    config.global.network.proxy_password = Some(String::new());
+    Ok(Table::try_from(config)?)
+}
+
+/// Dotted keys of all the settings, e.g. `preferences.port`
+pub fn keys() -> Result<Vec<String>, Error> {
+    let mut keys = Vec::new();
+    collect_keys(&template()?, "", &mut keys);
+    Ok(keys)
+}
+
+fn collect_keys(table: &Table, prefix: &str, keys: &mut Vec<String>) {
+    for (name, value) in table {
+        let key = format!("{prefix}{name}");
+        match value {
+            // Variables are arbitrary
+            Value::Table(_) if key == "env" => {}
+            Value::Table(table) => collect_keys(table, &format!("{key}."), keys),
+            _ => keys.push(key),
+        }
+    }
+}
+
+/// Checks the setting exists, suggests similar ones if not
+fn check_key(key: &str) -> Result<(), Error> {
+    if let Some(name) = key.strip_prefix(ENV_PREFIX) {
+        if name.is_empty() || name.contains('.') {
+            return Err(err!("Expected a variable name after '{ENV_PREFIX}'"));
+        }
+        return Ok(());
+    }
+    let keys = keys()?;
+    if keys.iter().any(|known| known == key) {
+        return Ok(());
+    }
+    let last = key.rsplit('.').next().unwrap_or(key);
+    let similar: Vec<&String> = keys
+        .iter()
+        .filter(|known| {
+            let known_last = known.rsplit('.').next().unwrap_or(known);
+            distance(known, key) <= 2 || distance(known_last, last) <= 2
+        })
+        .take(3)
+        .collect();
+    let mut message = format!("Unknown setting '{key}'.");
+    if similar.is_empty() {
+        message.push_str(" Run `knowledge config list` to see all settings.");
+    } else {
+        let similar: Vec<&str> = similar.iter().map(|key| key.as_str()).collect();
+        message.push_str(&format!(" Did you mean: {}?", similar.join(", ")));
+    }
+    Err(Error::msg(message))
+}
+
+/// Settings that are not printed with all the settings
+pub fn is_secret(key: &str) -> bool {
+    key.ends_with("token") || key.ends_with("password")
+}
+
+/// The value of the setting, `None` if it's not set
+pub fn get(config: &UserConfig, key: &str) -> Result<Option<Value>, Error> {
+    check_key(key)?;
+    let table = Table::try_from(config)?;
+    Ok(lookup(&table, key).cloned())
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let Some((first, rest)) = key.split_once('.') else {
+        return table.get(key);
+    };
+    match table.get(first)? {
+        Value::Table(inner) => lookup(inner, rest),
+        _ => None,
+    }
+}
+
+/// Sets the setting in the document of the config file and validates it
+pub fn set(doc: &mut DocumentMut, key: &str, raw: &str) -> Result<(), Error> {
+    check_key(key)?;
+    let template = template()?;
+    let is_string =
+        key.starts_with(ENV_PREFIX) || matches!(lookup(&template, key), Some(Value::String(_)));
+    let value: toml_edit::Value = if is_string {
+        raw.into()
+    } else {
+        raw.parse()
+            .map_err(|_| err!("Can't parse '{raw}' as a value of '{key}'"))?
+    };
+    let (path, name) = split_key(key);
+    let mut table = doc.as_table_mut();
+    for part in path {
+        let item = table
+            .entry(part)
+            .or_insert_with(|| Item::Table(toml_edit::Table::new()));
+        table = item
+            .as_table_mut()
+            .ok_or_else(|| err!("'{part}' is not a table in the config"))?;
+    }
+    table.insert(name, Item::Value(value));
+    let config = toml::from_str::<UserConfig>(&doc.to_string())
+        .map_err(|e| err!("Invalid value of '{key}': {}", e.message()))?;
+    config.preferences.check()
+}
+
+/// Removes the setting from the document, the default is used then
+pub fn unset(doc: &mut DocumentMut, key: &str) -> Result<bool, Error> {
+    check_key(key)?;
+    let (path, name) = split_key(key);
+    let mut table = doc.as_table_mut();
+    for part in path {
+        match table.get_mut(part).and_then(Item::as_table_mut) {
+            Some(inner) => table = inner,
+            None => return Ok(false),
+        }
+    }
+    Ok(table.remove(name).is_some())
+}
+
+/// Checks the document is a valid config
+pub fn validate(doc: &DocumentMut) -> Result<UserConfig, Error> {
+    let config: UserConfig = toml::from_str(&doc.to_string())?;
+    config.preferences.check()?;
+    Ok(config)
+}
+
+fn split_key(key: &str) -> (Vec<&str>, &str) {
+    if let Some(name) = key.strip_prefix(ENV_PREFIX) {
+        return (vec!["env"], name);
+    }
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let name = parts.pop().unwrap_or(key);
+    (parts, name)
+}
+
+/// The number of edits to turn one word into another
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            row.push((prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
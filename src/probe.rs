@@ -1,16 +1,20 @@
+use crate::http::HttpClient;
 use anyhow::{anyhow as err, Error};
-use reqwest::Client;
 use tokio::time::{sleep, Duration};
 
 pub struct ProbeTool {
-    client: Client,
+    client: HttpClient,
+}
+
+impl Default for ProbeTool {
+    fn default() -> Self {
+        Self::new(HttpClient::default())
+    }
 }
 
 impl ProbeTool {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+    pub fn new(client: HttpClient) -> Self {
+        Self { client }
     }
 
     pub async fn probe(&self, url: &str) -> Result<(), Error> {
@@ -24,11 +28,40 @@ impl ProbeTool {
         Err(err!("The app is still not available at: {url}"))
     }
 
-    pub async fn probe_is_free(&self, url: &str) -> Result<(), Error> {
-        if self.client.get(url).send().await.is_err() {
-            Ok(())
-        } else {
-            Err(err!("The port is not free: {url}"))
+    pub async fn is_free(&self, url: &str) -> bool {
+        self.client.get(url).send().await.is_err()
+    }
+
+    /// Checks the app responds successfully
+    pub async fn is_healthy(&self, url: &str) -> bool {
+        match self.client.get(url).send().await {
+            Ok(resp) => resp.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    /// Resolves when the app fails to respond `failures` times in a row.
+    ///
+    /// Never resolves if the `interval` is zero.
+    pub async fn watch(&self, url: &str, interval: Duration, failures: u32) {
+        if interval.is_zero() {
+            return futures::future::pending().await;
+        }
+        let mut failed = 0;
+        loop {
+            sleep(interval).await;
+            let resp = self.client.get(url).timeout(interval).send().await;
+            match resp {
+                Ok(resp) if resp.status().is_success() => {
+                    failed = 0;
+                }
+                _ => {
+                    failed += 1;
+                    if failed >= failures {
+                        return;
+                    }
+                }
+            }
         }
     }
 }
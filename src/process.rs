@@ -0,0 +1,274 @@
+use anyhow::Error;
+use tokio::time::{sleep, Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// A request to shut down received by the launcher
+#[derive(Debug, Clone, Copy)]
+pub enum Shutdown {
+    Interrupt,
+    Terminate,
+    Hangup,
+}
+
+#[cfg(unix)]
+impl From<Shutdown> for nix::sys::signal::Signal {
+    fn from(shutdown: Shutdown) -> Self {
+        match shutdown {
+            Shutdown::Interrupt => Self::SIGINT,
+            Shutdown::Terminate => Self::SIGTERM,
+            Shutdown::Hangup => Self::SIGHUP,
+        }
+    }
+}
+
+/// Listens for `SIGINT`, `SIGTERM` and `SIGHUP` (`Ctrl-C` on Windows).
+///
+/// Signals are caught from the moment the listener is created,
+/// so it has to exist before the app is spawned.
+pub struct ShutdownListener {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    ctrl_c: tokio::signal::windows::CtrlC,
+}
+
+impl ShutdownListener {
+    pub fn new() -> Result<Self, Error> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+                hangup: signal(SignalKind::hangup())?,
+            })
+        }
+        #[cfg(windows)]
+        {
+            Ok(Self {
+                ctrl_c: tokio::signal::windows::ctrl_c()?,
+            })
+        }
+    }
+
+    /// Waits for the next request to shut down
+    pub async fn recv(&mut self) -> Shutdown {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => Shutdown::Interrupt,
+                _ = self.terminate.recv() => Shutdown::Terminate,
+                _ = self.hangup.recv() => Shutdown::Hangup,
+            }
+        }
+        #[cfg(windows)]
+        {
+            self.ctrl_c.recv().await;
+            Shutdown::Interrupt
+        }
+    }
+}
+
+/// Checks the process with the `pid` is still running
+pub fn is_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        use nix::errno::Errno;
+        use nix::sys::signal::kill;
+        use nix::unistd::Pid;
+        // No signal is sent, only the existence of the process is checked.
+        // `EPERM` means the process exists, but belongs to another user.
+        !matches!(kill(Pid::from_raw(pid as i32), None), Err(Errno::ESRCH))
+    }
+    #[cfg(windows)]
+    {
+        let filter = format!("PID eq {pid}");
+        std::process::Command::new("tasklist")
+            .args(["/FI", &filter, "/NH"])
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).contains(&pid.to_string()))
+            .unwrap_or(false)
+    }
+}
+
+/// The moment the process was started, in clock ticks after the boot.
+///
+/// Tells the process from another one that got the same `pid` later,
+/// e.g. after a reboot. Supported on Linux only, since it reads `/proc`.
+pub fn start_time(pid: u32) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        // The name of the process in parentheses may contain spaces
+        let (_, fields) = stat.rsplit_once(')')?;
+        // The state is the 3rd field and the start time is the 22nd one
+        fields.split_whitespace().nth(19)?.parse().ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+/// Checks any process of the group led by `pid` is still running
+fn is_group_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        use nix::errno::Errno;
+        use nix::sys::signal::killpg;
+        use nix::unistd::Pid;
+        match killpg(Pid::from_raw(pid as i32), None) {
+            // Not a group leader, the app was started without a group
+            Err(Errno::ESRCH) => is_alive(pid),
+            _ => true,
+        }
+    }
+    #[cfg(windows)]
+    {
+        is_alive(pid)
+    }
+}
+
+/// Sends the signal to the whole process group of the app.
+///
+/// Falls back to the process itself if it doesn't lead a group.
+/// On Windows the console delivers `Ctrl-C` to the app directly.
+pub fn forward(pid: u32, shutdown: Shutdown) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use nix::errno::Errno;
+        use nix::sys::signal::{kill, killpg, Signal};
+        use nix::unistd::Pid;
+        let pid = Pid::from_raw(pid as i32);
+        let signal = Signal::from(shutdown);
+        match killpg(pid, signal) {
+            Err(Errno::ESRCH) => match kill(pid, signal) {
+                Err(Errno::ESRCH) => {}
+                res => res?,
+            },
+            res => res?,
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = (pid, shutdown);
+    }
+    Ok(())
+}
+
+/// Kills the process and all the processes of its group immediately
+pub fn kill_group(pid: u32) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use nix::errno::Errno;
+        use nix::sys::signal::{kill, killpg, Signal};
+        use nix::unistd::Pid;
+        let pid = Pid::from_raw(pid as i32);
+        match killpg(pid, Signal::SIGKILL) {
+            Err(Errno::ESRCH) => match kill(pid, Signal::SIGKILL) {
+                Err(Errno::ESRCH) => {}
+                res => res?,
+            },
+            res => res?,
+        }
+    }
+    #[cfg(windows)]
+    {
+        std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .output()?;
+    }
+    Ok(())
+}
+
+/// Asks the process group to stop and kills it if it's still alive after the grace period
+pub async fn terminate(pid: u32, grace_period: Duration) -> Result<(), Error> {
+    #[cfg(unix)]
+    forward(pid, Shutdown::Terminate)?;
+    #[cfg(windows)]
+    {
+        tokio::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T"])
+            .output()
+            .await?;
+    }
+    let deadline = Instant::now() + grace_period;
+    while Instant::now() < deadline {
+        if !is_group_alive(pid) {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    kill_group(pid)
+}
+
+/// Detects a process that listens the `port`.
+///
+/// Supported on Linux only, since it reads the `/proc` filesystem.
+pub fn port_owner(port: u16) -> Option<ProcessInfo> {
+    #[cfg(target_os = "linux")]
+    {
+        let inode = listening_inode(port)?;
+        let socket = format!("socket:[{inode}]");
+        for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+                // No access to descriptors of other users
+                continue;
+            };
+            for fd in fds.flatten() {
+                if let Ok(link) = std::fs::read_link(fd.path()) {
+                    if link.as_os_str() == socket.as_str() {
+                        let name = std::fs::read_to_string(entry.path().join("comm"))
+                            .map(|name| name.trim().to_string())
+                            .unwrap_or_else(|_| "unknown".into());
+                        return Some(ProcessInfo { pid, name });
+                    }
+                }
+            }
+        }
+        None
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = port;
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn listening_inode(port: u16) -> Option<u64> {
+    const TCP_LISTEN: &str = "0A";
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(contents) = std::fs::read_to_string(table) else {
+            continue;
+        };
+        // Skips the header line
+        for line in contents.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                continue;
+            }
+            let local_port = fields[1]
+                .rsplit(':')
+                .next()
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok());
+            if local_port == Some(port) {
+                return fields[9].parse().ok();
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,182 @@
+use anyhow::{anyhow as err, Error};
+use semver::VersionReq;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+pub const RIDE_DIR: &str = ".ride";
+pub const CONFIG_FILE: &str = "launcher.toml";
+pub const NOTES_FILE: &str = "notes.toml";
+
+/// Settings of the launcher for a practice folder
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    /// A requirement to the version of the app
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<VersionReq>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub course: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct Project {
+    pub path: PathBuf,
+    pub config: ProjectConfig,
+}
+
+impl Project {
+    /// Finds the closest `.ride/launcher.toml` walking up from the folder
+    pub async fn discover(folder: &Path) -> Result<Option<Self>, Error> {
+        for dir in folder.ancestors() {
+            let mut path = dir.to_path_buf();
+            path.push(RIDE_DIR);
+            path.push(CONFIG_FILE);
+            match fs::read_to_string(&path).await {
+                Ok(contents) => {
+                    let config = toml::from_str(&contents)
+                        .map_err(|e| err!("Can't read {}: {e}", path.display()))?;
+                    return Ok(Some(Self { path, config }));
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Where the value of a setting came from
+#[derive(Debug, Clone)]
+pub enum Source {
+    User(PathBuf),
+    Project(PathBuf),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::User(path) | Self::Project(path) => write!(f, "{}", path.display()),
+            Self::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sourced<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Sourced<T> {
+    pub fn new(value: T, source: Source) -> Self {
+        Self { value, source }
+    }
+}
+
+/// Settings of the launch merged from all the sources.
+///
+/// The command line overrides the project, that overrides the user config.
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub version: Option<Sourced<VersionReq>>,
+    pub port: Option<Sourced<u16>>,
+    pub course: Option<Sourced<String>>,
+    pub args: Vec<Sourced<String>>,
+    pub env: BTreeMap<String, Sourced<String>>,
+}
+
+impl Settings {
+    pub fn add_user(&mut self, path: &Path, port: Option<u16>, env: &BTreeMap<String, String>) {
+        let source = Source::User(path.to_path_buf());
+        if let Some(port) = port {
+            self.port = Some(Sourced::new(port, source.clone()));
+        }
+        for (name, value) in env {
+            let value = Sourced::new(value.clone(), source.clone());
+            self.env.insert(name.clone(), value);
+        }
+    }
+
+    pub fn add_project(&mut self, project: &Project) {
+        let source = Source::Project(project.path.clone());
+        let config = &project.config;
+        if let Some(version) = config.version.clone() {
+            self.version = Some(Sourced::new(version, source.clone()));
+        }
+        if let Some(port) = config.port {
+            self.port = Some(Sourced::new(port, source.clone()));
+        }
+        if let Some(course) = config.course.clone() {
+            self.course = Some(Sourced::new(course, source.clone()));
+        }
+        for arg in &config.args {
+            self.args.push(Sourced::new(arg.clone(), source.clone()));
+        }
+        for (name, value) in &config.env {
+            let value = Sourced::new(value.clone(), source.clone());
+            self.env.insert(name.clone(), value);
+        }
+    }
+
+    pub fn add_command_line(
+        &mut self,
+        port: Option<u16>,
+        args: &[String],
+        env: &[(String, String)],
+    ) {
+        let source = Source::CommandLine;
+        if let Some(port) = port {
+            self.port = Some(Sourced::new(port, source.clone()));
+        }
+        for arg in args {
+            self.args.push(Sourced::new(arg.clone(), source.clone()));
+        }
+        for (name, value) in env {
+            let value = Sourced::new(value.clone(), source.clone());
+            self.env.insert(name.clone(), value);
+        }
+    }
+
+    pub fn args(&self) -> Vec<String> {
+        self.args.iter().map(|arg| arg.value.clone()).collect()
+    }
+
+    pub fn env(&self) -> BTreeMap<String, String> {
+        self.env
+            .iter()
+            .map(|(name, value)| (name.clone(), value.value.clone()))
+            .collect()
+    }
+
+    /// Lines of `name = value (source)` for every configured setting
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(version) = &self.version {
+            lines.push(format!("version = {} ({})", version.value, version.source));
+        }
+        if let Some(port) = &self.port {
+            lines.push(format!("port = {} ({})", port.value, port.source));
+        }
+        if let Some(course) = &self.course {
+            lines.push(format!("course = {} ({})", course.value, course.source));
+        }
+        for arg in &self.args {
+            lines.push(format!("arg {} ({})", arg.value, arg.source));
+        }
+        for (name, value) in &self.env {
+            lines.push(format!("env {name}={} ({})", value.value, value.source));
+        }
+        lines
+    }
+}
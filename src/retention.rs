@@ -0,0 +1,199 @@
+use crate::app_info;
+use crate::cacher::Cacher;
+use crate::disk;
+use anyhow::Error;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs;
+
+/// What is kept in the cache by the garbage collection
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// How many of the latest installed versions are kept
+    pub keep_versions: usize,
+    /// Versions that are never removed
+    pub pinned: Vec<Version>,
+    /// Bytes the cache may take, the oldest files are removed above it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cache_size: Option<u64>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            keep_versions: 2,
+            pinned: Vec::new(),
+            max_cache_size: None,
+        }
+    }
+}
+
+/// A file or a folder to remove
+#[derive(Debug)]
+pub struct Garbage {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Finds files of the cache that are outside the retention policy
+pub async fn collect(cacher: &Cacher) -> Result<Vec<Garbage>, Error> {
+    let config = &cacher.config().global.retention;
+    let current = cacher.ri_learn.version.as_ref();
+    let is_protected =
+        |version: &Version| current == Some(version) || config.pinned.contains(version);
+
+    let mut garbage = Vec::new();
+    // The oldest first, removed first if the cache is too big
+    let mut spare = Vec::new();
+    let installed = cacher.installed_versions(&app_info::LEARN).await?;
+    let mut kept = 0;
+    for version in installed.iter().rev() {
+        let path = cacher.version_dir(&app_info::LEARN, version);
+        let size = disk::size_of(&path).await?;
+        let item = Garbage { path, size };
+        if is_protected(version) {
+            kept += 1;
+        } else if kept < config.keep_versions {
+            kept += 1;
+            spare.insert(0, item);
+        } else {
+            garbage.push(item);
+        }
+    }
+    for path in cacher.legacy_binaries().await? {
+        let size = disk::size_of(&path).await?;
+        garbage.push(Garbage { path, size });
+    }
+
+    if let Some(max_size) = config.max_cache_size {
+        spare.extend(templates_by_age(cacher).await?);
+        let removed: u64 = garbage.iter().map(|item| item.size).sum();
+        let mut size = disk::size_of(cacher.cache_dir())
+            .await?
+            .saturating_sub(removed);
+        for item in spare {
+            if size <= max_size {
+                break;
+            }
+            size = size.saturating_sub(item.size);
+            garbage.push(item);
+        }
+    }
+    Ok(garbage)
+}
+
+/// Removes the garbage and returns how many bytes are freed
+pub async fn remove(garbage: &[Garbage]) -> Result<u64, Error> {
+    for item in garbage {
+        disk::remove(&item.path).await?;
+    }
+    Ok(garbage.iter().map(|item| item.size).sum())
+}
+
+async fn templates_by_age(cacher: &Cacher) -> Result<Vec<Garbage>, Error> {
+    let mut templates = Vec::new();
+    let mut entries = fs::read_dir(cacher.templates_dir()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let item = Garbage {
+            path: entry.path(),
+            size: metadata.len(),
+        };
+        templates.push((metadata.modified()?, item));
+    }
+    templates.sort_by_key(|(modified, _)| *modified);
+    Ok(templates.into_iter().map(|(_, item)| item).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// The cache with versions of the app taking 1 KiB each
+    async fn cacher(home: &TempDir, config: &str, versions: &[&str], current: &str) -> Cacher {
+        let mut cacher = Cacher::create(Some(home.path()), false, None)
+            .await
+            .unwrap();
+        let config_path = cacher.config_path().clone();
+        fs::create_dir_all(config_path.parent().unwrap())
+            .await
+            .unwrap();
+        fs::write(&config_path, config).await.unwrap();
+        cacher.initialize().await.unwrap();
+        for version in versions {
+            let dir = cacher.version_dir(&app_info::LEARN, &version.parse().unwrap());
+            fs::create_dir_all(&dir).await.unwrap();
+            fs::write(dir.join(app_info::LEARN.name), vec![0; 1024])
+                .await
+                .unwrap();
+        }
+        cacher.ri_learn.version = Some(current.parse().unwrap());
+        cacher
+    }
+
+    fn names(garbage: &[Garbage]) -> Vec<String> {
+        garbage
+            .iter()
+            .map(|item| item.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    const VERSIONS: [&str; 4] = ["1.0.0", "1.1.0", "1.2.0", "1.3.0"];
+
+    #[tokio::test]
+    async fn keeps_the_latest_and_the_current_versions() {
+        let home = TempDir::new().unwrap();
+        let cacher = cacher(&home, "", &VERSIONS, "1.0.0").await;
+
+        let garbage = collect(&cacher).await.unwrap();
+
+        assert_eq!(names(&garbage), ["1.1.0"]);
+        assert_eq!(garbage[0].size, 1024);
+    }
+
+    #[tokio::test]
+    async fn keeps_pinned_versions() {
+        let home = TempDir::new().unwrap();
+        let config = "[global.retention]\nkeep_versions = 1\npinned = [\"1.1.0\"]\n";
+        let cacher = cacher(&home, config, &VERSIONS, "1.3.0").await;
+
+        let garbage = collect(&cacher).await.unwrap();
+
+        assert_eq!(names(&garbage), ["1.2.0", "1.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn removes_kept_versions_and_templates_above_the_limit() {
+        let home = TempDir::new().unwrap();
+        let config = "[global.retention]\nmax_cache_size = 0\n";
+        let cacher = cacher(&home, config, &VERSIONS, "1.3.0").await;
+        let template = cacher
+            .templates_dir()
+            .join("ri-lab-template-rust-1.3.0.tar.gz");
+        fs::write(&template, "template").await.unwrap();
+
+        let garbage = collect(&cacher).await.unwrap();
+
+        let expected = [
+            "1.1.0",
+            "1.0.0",
+            "1.2.0",
+            "ri-lab-template-rust-1.3.0.tar.gz",
+        ];
+        assert_eq!(names(&garbage), expected);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_cache_below_the_limit() {
+        let home = TempDir::new().unwrap();
+        let config = "[global.retention]\nmax_cache_size = 1048576\n";
+        let cacher = cacher(&home, config, &VERSIONS, "1.3.0").await;
+
+        let garbage = collect(&cacher).await.unwrap();
+
+        assert_eq!(names(&garbage), ["1.1.0", "1.0.0"]);
+    }
+}
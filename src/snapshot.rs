@@ -0,0 +1,561 @@
+use crate::disk;
+use crate::project::{NOTES_FILE, RIDE_DIR};
+use anyhow::{anyhow as err, Error};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::{Compression, CrcReader};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tar::{Archive, Builder, Header};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Take a snapshot every time the app is launched
+    pub auto: bool,
+    /// How many snapshots are kept for a folder
+    pub max_count: usize,
+    /// Bytes of files of the folder, bigger folders are not saved
+    pub max_size: u64,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            max_count: 20,
+            max_size: 100 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SnapshotInfo {
+    pub id: u32,
+    pub workdir: PathBuf,
+    pub created: DateTime<Utc>,
+    pub files: usize,
+    pub size: u64,
+    pub auto: bool,
+}
+
+/// A difference between a snapshot and the folder
+#[derive(Debug)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Modified(String),
+}
+
+/// What is known about a file of a snapshot to detect changes without unpacking it
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+struct FileStamp {
+    size: u64,
+    /// Nanoseconds since the epoch, unknown for snapshots of older launchers
+    modified: Option<u64>,
+    crc: u32,
+}
+
+impl FileStamp {
+    fn is_same(&self, path: &Path) -> Result<bool, Error> {
+        let metadata = fs::metadata(path)?;
+        if metadata.len() != self.size {
+            return Ok(false);
+        }
+        if self.modified.is_some() && modified_nanos(&metadata) == self.modified {
+            return Ok(true);
+        }
+        // The time changes on saving without changes, the content decides then
+        let mut reader = CrcReader::new(File::open(path)?);
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(reader.crc().sum() == self.crc)
+    }
+}
+
+fn modified_nanos(metadata: &Metadata) -> Option<u64> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Notes {
+    #[serde(default)]
+    ignore: Vec<IgnoreRule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IgnoreRule {
+    glob: String,
+}
+
+/// Compressed copies of working folders
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    /// Folders of the launcher that are never saved, even inside a working folder
+    excluded: Vec<PathBuf>,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: PathBuf, excluded: Vec<PathBuf>) -> Self {
+        let excluded = excluded
+            .into_iter()
+            .map(|path| fs::canonicalize(&path).unwrap_or(path))
+            .collect();
+        Self { dir, excluded }
+    }
+
+    pub async fn list(&self, workdir: &Path) -> Result<Vec<SnapshotInfo>, Error> {
+        let store = self.clone();
+        let workdir = workdir.to_path_buf();
+        tokio::task::spawn_blocking(move || store.list_sync(&canonical(&workdir))).await?
+    }
+
+    /// Takes a snapshot unless the folder is the same as in the latest one
+    pub async fn create(
+        &self,
+        workdir: &Path,
+        auto: bool,
+        config: &SnapshotConfig,
+    ) -> Result<Option<SnapshotInfo>, Error> {
+        let store = self.clone();
+        let workdir = workdir.to_path_buf();
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || {
+            store.create_sync(&canonical(&workdir), auto, &config, None)
+        })
+        .await?
+    }
+
+    pub async fn diff(&self, workdir: &Path, id: u32) -> Result<Vec<Change>, Error> {
+        let store = self.clone();
+        let workdir = workdir.to_path_buf();
+        tokio::task::spawn_blocking(move || store.diff_sync(&canonical(&workdir), id)).await?
+    }
+
+    /// Makes the folder the same as the snapshot, ignored files are kept.
+    ///
+    /// The current state is saved to a new snapshot first, it's returned if taken.
+    pub async fn restore(
+        &self,
+        workdir: &Path,
+        id: u32,
+        config: &SnapshotConfig,
+    ) -> Result<Option<SnapshotInfo>, Error> {
+        let store = self.clone();
+        let workdir = workdir.to_path_buf();
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || store.restore_sync(&canonical(&workdir), id, &config))
+            .await?
+    }
+
+    /// The folder of snapshots is named after the hash of the full path,
+    /// the name of the working folder is only added to make it recognizable.
+    fn folder_dir(&self, workdir: &Path) -> PathBuf {
+        let name: String = workdir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .take(32)
+            .collect();
+        let hash = path_hash(workdir);
+        self.dir.join(format!("{name}-{hash:016x}"))
+    }
+
+    fn archive_path(&self, workdir: &Path, id: u32) -> PathBuf {
+        self.folder_dir(workdir).join(format!("{id}.tar.gz"))
+    }
+
+    fn info_path(&self, workdir: &Path, id: u32) -> PathBuf {
+        self.folder_dir(workdir).join(format!("{id}.toml"))
+    }
+
+    fn manifest_path(&self, workdir: &Path, id: u32) -> PathBuf {
+        self.folder_dir(workdir).join(format!("{id}.json"))
+    }
+
+    /// Snapshots of the folder, the folder of snapshots may be shared
+    /// with another working folder if their hashes are the same.
+    fn list_sync(&self, workdir: &Path) -> Result<Vec<SnapshotInfo>, Error> {
+        let mut snapshots = self.read_infos(workdir)?;
+        snapshots.retain(|info| info.workdir == workdir);
+        Ok(snapshots)
+    }
+
+    /// All snapshots stored in the folder of snapshots of the working folder
+    fn read_infos(&self, workdir: &Path) -> Result<Vec<SnapshotInfo>, Error> {
+        let entries = match fs::read_dir(self.folder_dir(workdir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("toml") {
+                let info: SnapshotInfo = toml::from_str(&fs::read_to_string(path)?)?;
+                snapshots.push(info);
+            }
+        }
+        snapshots.sort_by_key(|info| info.id);
+        Ok(snapshots)
+    }
+
+    fn get_sync(&self, workdir: &Path, id: u32) -> Result<SnapshotInfo, Error> {
+        self.list_sync(workdir)?
+            .into_iter()
+            .find(|info| info.id == id)
+            .ok_or_else(|| err!("The snapshot {id} doesn't exist"))
+    }
+
+    fn create_sync(
+        &self,
+        workdir: &Path,
+        auto: bool,
+        config: &SnapshotConfig,
+        keep: Option<u32>,
+    ) -> Result<Option<SnapshotInfo>, Error> {
+        let snapshots = self.list_sync(workdir)?;
+        if let Some(latest) = snapshots.last() {
+            if self.diff_sync(workdir, latest.id)?.is_empty() {
+                return Ok(None);
+            }
+        }
+        let files = self.collect_files(workdir)?;
+        let mut total = 0;
+        for file in &files {
+            total += fs::metadata(workdir.join(file))?.len();
+        }
+        if total > config.max_size {
+            return Err(err!(
+                "The folder has {} of files, more than the limit of {} (global.snapshots.max_size)",
+                disk::format_size(total),
+                disk::format_size(config.max_size)
+            ));
+        }
+        // Ids of snapshots of other folders with the same hash are not reused
+        let stored = self.read_infos(workdir)?;
+        let id = stored.iter().map(|info| info.id).max().unwrap_or_default() + 1;
+        fs::create_dir_all(self.folder_dir(workdir))?;
+        let archive_path = self.archive_path(workdir, id);
+        let encoder = GzEncoder::new(File::create(&archive_path)?, Compression::default());
+        let mut builder = Builder::new(encoder);
+        let mut manifest = BTreeMap::new();
+        for file in &files {
+            let path = workdir.join(file);
+            let metadata = fs::metadata(&path)?;
+            let mut header = Header::new_gnu();
+            header.set_metadata(&metadata);
+            let mut reader = CrcReader::new(File::open(&path)?);
+            builder.append_data(&mut header, file, &mut reader)?;
+            let stamp = FileStamp {
+                size: metadata.len(),
+                modified: modified_nanos(&metadata),
+                crc: reader.crc().sum(),
+            };
+            manifest.insert(file.to_string_lossy().to_string(), stamp);
+        }
+        builder.into_inner()?.finish()?;
+        fs::write(
+            self.manifest_path(workdir, id),
+            serde_json::to_string(&manifest)?,
+        )?;
+        let info = SnapshotInfo {
+            id,
+            workdir: workdir.to_path_buf(),
+            created: Utc::now(),
+            files: files.len(),
+            size: fs::metadata(&archive_path)?.len(),
+            auto,
+        };
+        fs::write(self.info_path(workdir, id), toml::to_string(&info)?)?;
+
+        // Removes the oldest snapshots
+        let outdated = (snapshots.len() + 1).saturating_sub(config.max_count.max(1));
+        for old in snapshots
+            .iter()
+            .filter(|old| Some(old.id) != keep)
+            .take(outdated)
+        {
+            fs::remove_file(self.archive_path(workdir, old.id))?;
+            fs::remove_file(self.info_path(workdir, old.id))?;
+            // Snapshots of older launchers have no manifest
+            match fs::remove_file(self.manifest_path(workdir, old.id)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(Some(info))
+    }
+
+    /// Stamps of files of the snapshot.
+    ///
+    /// Snapshots of older launchers have no manifest, the archive is read then.
+    fn read_manifest(&self, workdir: &Path, id: u32) -> Result<BTreeMap<String, FileStamp>, Error> {
+        let info = self.get_sync(workdir, id)?;
+        match fs::read_to_string(self.manifest_path(&info.workdir, id)) {
+            Ok(contents) => return Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let file = File::open(self.archive_path(&info.workdir, id))?;
+        let mut archive = Archive::new(GzDecoder::new(file));
+        let mut manifest = BTreeMap::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let path = entry.path()?.to_string_lossy().to_string();
+            let size = entry.header().size()?;
+            let mut reader = CrcReader::new(entry);
+            io::copy(&mut reader, &mut io::sink())?;
+            let stamp = FileStamp {
+                size,
+                modified: None,
+                crc: reader.crc().sum(),
+            };
+            manifest.insert(path, stamp);
+        }
+        Ok(manifest)
+    }
+
+    fn diff_sync(&self, workdir: &Path, id: u32) -> Result<Vec<Change>, Error> {
+        let mut stored = self.read_manifest(workdir, id)?;
+        let mut changes = Vec::new();
+        for file in self.collect_files(workdir)? {
+            let name = file.to_string_lossy().to_string();
+            match stored.remove(&name) {
+                None => changes.push(Change::Added(name)),
+                Some(stamp) => {
+                    if !stamp.is_same(&workdir.join(&file))? {
+                        changes.push(Change::Modified(name));
+                    }
+                }
+            }
+        }
+        changes.extend(stored.into_keys().map(Change::Removed));
+        Ok(changes)
+    }
+
+    fn restore_sync(
+        &self,
+        workdir: &Path,
+        id: u32,
+        config: &SnapshotConfig,
+    ) -> Result<Option<SnapshotInfo>, Error> {
+        let info = self.get_sync(workdir, id)?;
+        let stored = self.read_manifest(workdir, id)?;
+        // The restored snapshot is kept even if it's the oldest one
+        let backup = self.create_sync(workdir, false, config, Some(id))?;
+        for file in self.collect_files(workdir)? {
+            if !stored.contains_key(file.to_string_lossy().as_ref()) {
+                fs::remove_file(workdir.join(file))?;
+            }
+        }
+        let file = File::open(self.archive_path(&info.workdir, id))?;
+        Archive::new(GzDecoder::new(file)).unpack(workdir)?;
+        Ok(backup)
+    }
+
+    /// Relative paths of all the files of the folder that are not ignored
+    fn collect_files(&self, workdir: &Path) -> Result<Vec<PathBuf>, Error> {
+        let patterns = ignore_patterns(workdir)?;
+        let is_ignored = |rel: &str, is_dir: bool| {
+            patterns.iter().any(|pattern| {
+                pattern.matches(rel)
+                    || is_dir
+                        && (pattern.as_str().strip_suffix("/*") == Some(rel)
+                            || pattern.as_str().strip_suffix("/**") == Some(rel))
+            })
+        };
+        let mut files = Vec::new();
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(workdir.join(&dir))? {
+                let entry = entry?;
+                let rel = dir.join(entry.file_name());
+                let rel_str = rel.to_string_lossy().replace('\\', "/");
+                let file_type = entry.file_type()?;
+                if is_ignored(&rel_str, file_type.is_dir()) {
+                    continue;
+                }
+                if file_type.is_dir() {
+                    if !self.is_excluded_dir(workdir, &dir, &entry.path()) {
+                        dirs.push(rel);
+                    }
+                } else if file_type.is_file() {
+                    files.push(rel);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Folders that are never a part of the practice
+    fn is_excluded_dir(&self, workdir: &Path, parent: &Path, path: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        // The repository of the user
+        name == ".git"
+            // Build artifacts of Cargo
+            || name == "target" && workdir.join(parent).join("Cargo.toml").is_file()
+            // Caches and snapshots of the launcher itself
+            || self.excluded.iter().any(|excluded| excluded == path)
+    }
+}
+
+/// Paths are compared as they are resolved, e.g. with symlinks followed
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A 64-bit FNV-1a hash, it never changes between versions of the launcher
+fn path_hash(path: &Path) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    path.as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+        })
+}
+
+/// Reads ignore globs of the `.ride/notes.toml` file
+fn ignore_patterns(workdir: &Path) -> Result<Vec<Pattern>, Error> {
+    let path = workdir.join(RIDE_DIR).join(NOTES_FILE);
+    let notes: Notes = match fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => Notes::default(),
+        Err(e) => return Err(e.into()),
+    };
+    let patterns = notes
+        .ignore
+        .iter()
+        .map(|rule| Pattern::new(&rule.glob))
+        .collect::<Result<_, _>>()?;
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(home: &TempDir) -> SnapshotStore {
+        SnapshotStore::new(home.path().join("snapshots"), Vec::new())
+    }
+
+    fn workdir(home: &TempDir, name: &str) -> PathBuf {
+        let dir = home.path().join(name);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("notes.md"), "# Notes").unwrap();
+        canonical(&dir)
+    }
+
+    fn names(changes: &[Change]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|change| match change {
+                Change::Added(path) => format!("+{path}"),
+                Change::Removed(path) => format!("-{path}"),
+                Change::Modified(path) => format!("~{path}"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn creates_and_compares_snapshots() {
+        let home = TempDir::new().unwrap();
+        let store = store(&home);
+        let dir = workdir(&home, "practice");
+        let config = SnapshotConfig::default();
+
+        let info = store.create(&dir, false, &config).await.unwrap().unwrap();
+        assert_eq!((info.id, info.files), (1, 2));
+        assert!(store.create(&dir, false, &config).await.unwrap().is_none());
+
+        fs::write(dir.join("src/main.rs"), "fn main() { todo!() }").unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        fs::remove_file(dir.join("notes.md")).unwrap();
+        let changes = store.diff(&dir, 1).await.unwrap();
+        assert_eq!(
+            names(&changes),
+            ["+src/lib.rs", "~src/main.rs", "-notes.md"]
+        );
+
+        let info = store.create(&dir, true, &config).await.unwrap().unwrap();
+        assert_eq!(info.id, 2);
+        assert!(store.diff(&dir, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn restores_a_snapshot_and_keeps_ignored_files() {
+        let home = TempDir::new().unwrap();
+        let store = store(&home);
+        let dir = workdir(&home, "practice");
+        fs::create_dir_all(dir.join(RIDE_DIR)).unwrap();
+        let notes = "[[ignore]]\nglob = \"*.log\"\n";
+        fs::write(dir.join(RIDE_DIR).join(NOTES_FILE), notes).unwrap();
+        let config = SnapshotConfig::default();
+        store.create(&dir, false, &config).await.unwrap();
+
+        fs::write(dir.join("src/main.rs"), "broken").unwrap();
+        fs::write(dir.join("src/extra.rs"), "").unwrap();
+        fs::write(dir.join("run.log"), "output").unwrap();
+        let backup = store.restore(&dir, 1, &config).await.unwrap().unwrap();
+
+        assert_eq!(backup.id, 2);
+        let main = fs::read_to_string(dir.join("src/main.rs")).unwrap();
+        assert_eq!(main, "fn main() {}");
+        assert!(!dir.join("src/extra.rs").exists());
+        assert!(dir.join("run.log").exists());
+        assert!(store.diff(&dir, 1).await.unwrap().is_empty());
+        // The state before the restore is kept
+        let changes = store.diff(&dir, 2).await.unwrap();
+        assert_eq!(names(&changes), ["~src/main.rs", "-src/extra.rs"]);
+    }
+
+    #[tokio::test]
+    async fn separates_folders_with_similar_names() {
+        let home = TempDir::new().unwrap();
+        let store = store(&home);
+        let first = workdir(&home, "a-b");
+        let second = workdir(&home, "a_b");
+        let config = SnapshotConfig::default();
+        store.create(&first, false, &config).await.unwrap();
+
+        assert_ne!(store.folder_dir(&first), store.folder_dir(&second));
+        assert!(store.list(&second).await.unwrap().is_empty());
+        assert!(store.restore(&second, 1, &config).await.is_err());
+        assert!(second.join("notes.md").exists());
+    }
+
+    #[tokio::test]
+    async fn skips_snapshots_of_another_folder_with_the_same_hash() {
+        let home = TempDir::new().unwrap();
+        let store = store(&home);
+        let first = workdir(&home, "first");
+        let second = workdir(&home, "second");
+        let config = SnapshotConfig::default();
+        store.create(&first, false, &config).await.unwrap();
+        // Emulates a collision of hashes
+        fs::rename(store.folder_dir(&first), store.folder_dir(&second)).unwrap();
+
+        assert!(store.list(&second).await.unwrap().is_empty());
+        let info = store
+            .create(&second, false, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.id, 2);
+        assert_eq!(store.list(&second).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+/// How many lines of the app's output are kept for a crash report
+const TAIL_LINES: usize = 20;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    /// Restart the app if it crashed or hung
+    pub auto_restart: bool,
+    /// Give up after this number of crashes within the window
+    pub max_crashes: usize,
+    /// Seconds to count crashes in
+    pub crash_window: u64,
+    /// Seconds between health probes of the running app
+    pub health_interval: u64,
+    /// Failed probes in a row to consider the app hung
+    pub health_failures: u32,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            auto_restart: false,
+            max_crashes: 3,
+            crash_window: 60,
+            health_interval: 10,
+            health_failures: 3,
+        }
+    }
+}
+
+/// Counts recent crashes to detect a crash loop
+#[derive(Debug)]
+pub struct CrashTracker {
+    crashes: VecDeque<Instant>,
+    max_crashes: usize,
+    window: Duration,
+}
+
+impl CrashTracker {
+    pub fn new(config: &SupervisorConfig) -> Self {
+        Self {
+            crashes: VecDeque::new(),
+            max_crashes: config.max_crashes,
+            window: Duration::from_secs(config.crash_window),
+        }
+    }
+
+    /// Records a crash and returns `false` if the app is in a crash loop
+    pub fn record(&mut self) -> bool {
+        let now = Instant::now();
+        self.crashes.push_back(now);
+        while let Some(first) = self.crashes.front() {
+            if now.duration_since(*first) > self.window {
+                self.crashes.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.crashes.len() < self.max_crashes
+    }
+
+    pub fn recent(&self) -> usize {
+        self.crashes.len()
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Exponential delay before the next restart
+    pub fn backoff(&self) -> Duration {
+        let exp = self.crashes.len().saturating_sub(1).min(5) as u32;
+        (Duration::from_secs(1) * 2u32.pow(exp)).min(MAX_BACKOFF)
+    }
+}
+
+/// Keeps the last lines of a stream
+#[derive(Debug, Clone, Default)]
+pub struct OutputTail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl OutputTail {
+    pub fn push(&self, line: String) {
+        let mut tail = self.lines.lock().unwrap();
+        tail.push_back(line);
+        if tail.len() > TAIL_LINES {
+            tail.pop_front();
+        }
+    }
+
+    pub fn clear(&self) {
+        self.lines.lock().unwrap().clear();
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Explains why the app has exited
+pub fn describe(status: &ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("exited with code {code}");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("was terminated by signal {signal}");
+        }
+    }
+    "exited for an unknown reason".into()
+}
@@ -0,0 +1,112 @@
+use crate::project::{ProjectConfig, CONFIG_FILE, NOTES_FILE, RIDE_DIR};
+use anyhow::Error;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tar::Archive;
+use tokio::fs;
+
+const MAX_RECENT: usize = 10;
+
+const CARGO_TOML: &str = r#"[workspace]
+members = []
+resolver = "2"
+"#;
+
+const GITIGNORE: &str = "target/\n";
+
+const NOTES_TOML: &str = r#"[[ignore]]
+glob = "target/*"
+"#;
+
+/// Recently used working folders
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct History {
+    pub folders: Vec<PathBuf>,
+}
+
+impl History {
+    /// Moves the folder to the top of the list
+    pub fn touch(&mut self, folder: &Path) {
+        self.folders.retain(|recent| recent != folder);
+        self.folders.insert(0, folder.to_path_buf());
+        self.folders.truncate(MAX_RECENT);
+    }
+
+    /// Folders that still exist on the disk
+    pub fn existing(&self) -> Vec<PathBuf> {
+        self.folders
+            .iter()
+            .filter(|folder| folder.is_dir())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Explains why the folder is a bad place for the practice
+pub fn warning(folder: &Path) -> Option<String> {
+    if folder.parent().is_none() {
+        return Some("it's the root of the file system".into());
+    }
+    if dirs::home_dir().as_deref() == Some(folder) {
+        return Some("it's the home folder".into());
+    }
+    if tempfile::tempfile_in(folder).is_err() {
+        return Some("it's not writable".into());
+    }
+    None
+}
+
+/// Creates the folder if it doesn't exist.
+///
+/// Returns the absolute path and `true` if the folder was created.
+pub async fn ensure(folder: &Path) -> Result<(PathBuf, bool), Error> {
+    let created = if fs::try_exists(folder).await? {
+        false
+    } else {
+        fs::create_dir_all(folder).await?;
+        true
+    };
+    let folder = fs::canonicalize(folder).await?;
+    Ok((folder, created))
+}
+
+/// Checks the folder doesn't contain a workspace already
+pub async fn is_initialized(folder: &Path) -> Result<bool, Error> {
+    let mut ride_config = folder.join(RIDE_DIR);
+    ride_config.push(CONFIG_FILE);
+    let initialized =
+        fs::try_exists(folder.join("Cargo.toml")).await? || fs::try_exists(ride_config).await?;
+    Ok(initialized)
+}
+
+/// Unpacks a `tar.gz` template of a course into the folder
+pub fn unpack_template(archive: std::fs::File, folder: &Path) -> Result<(), Error> {
+    let tar = GzDecoder::new(archive);
+    Archive::new(tar).unpack(folder)?;
+    Ok(())
+}
+
+/// Adds files of the skeleton that are missing in the folder
+pub async fn scaffold(folder: &Path, course: Option<&str>) -> Result<(), Error> {
+    let ride_dir = folder.join(RIDE_DIR);
+    fs::create_dir_all(&ride_dir).await?;
+    write_missing(&folder.join("Cargo.toml"), CARGO_TOML).await?;
+    write_missing(&folder.join(".gitignore"), GITIGNORE).await?;
+    write_missing(&ride_dir.join(NOTES_FILE), NOTES_TOML).await?;
+    let config = ProjectConfig {
+        course: course.map(String::from),
+        ..ProjectConfig::default()
+    };
+    let contents = toml::to_string(&config)?;
+    write_missing(&ride_dir.join(CONFIG_FILE), &contents).await?;
+    Ok(())
+}
+
+async fn write_missing(path: &Path, contents: &str) -> Result<(), Error> {
+    if !fs::try_exists(path).await? {
+        fs::write(path, contents).await?;
+    }
+    Ok(())
+}